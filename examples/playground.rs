@@ -3,7 +3,6 @@
 use std::ops::Deref;
 // use viemo::memo::{CellIterMemo, Memo, OptionCellMemo, OptionNodeMemo, OwnedMemo, CellSliceMemo};
 use viemo::memo::{CellSliceMemo, NodeMemo, OptionCellMemo, OptionNodeMemo, OwnedMemo};
-use viemo::watcher::{Watcher, Watcher2};
 
 fn main() {
     use futures::StreamExt;
@@ -67,14 +66,16 @@ fn main() {
     //     }
     // };
 
-    // let mut iter_memo = CellIterMemo::new(&store, |root: &MyRoot, cx| root.elements.iter());
+    // let mut iter_memo = CellIterMemo::from_fn(&store, |root, _| Box::new(root.elements.iter()));
     //
-    let watcher = Watcher2::new(&store, cell_memo, owned_memo, |(cell, owned), cx| {
+    let watcher = Watcher::new(&store, (cell_memo, owned_memo), |(cell, owned), cx| {
         println!("{} {}", cell.deref(cx).a, owned);
+
+        Some(())
     });
 
     //
-    // let mut watcher = Watcher2::new(&store, cell_memo, node_memo);
+    // let mut watcher = Watcher::new(&store, (cell_memo, node_memo));
 
     // let render = async move {
     //     while let Some(view) = watcher.next().await {
@@ -1,8 +1,10 @@
 use std::mem;
 use std::ops::Deref;
 use std::ptr::NonNull;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, PoisonError};
 
+// The listener list is never left in an inconsistent state when a panic occurs while the lock is
+// held (e.g. when a broadcast callback panics), so lock poisoning is ignored throughout.
 type Shared<T> = Arc<Mutex<Option<NonNull<ListenerInternal<T>>>>>;
 
 pub struct Listener<T> {
@@ -33,7 +35,7 @@ impl<T> ListenerInternal<T> {
             ..
         } = self;
 
-        let mut lock = shared.lock().unwrap();
+        let mut lock = shared.lock().unwrap_or_else(PoisonError::into_inner);
 
         if let Some(mut next) = next {
             unsafe {
@@ -70,11 +72,14 @@ impl<T> Broadcaster<T> {
         }
     }
 
-    pub fn broadcast<F>(&self, f: F)
+    /// Calls `f` for every listener's value while holding the listener list's lock.
+    ///
+    /// `f` must not add or drop listeners of this broadcaster, as that would deadlock.
+    pub fn broadcast<F>(&self, mut f: F)
     where
-        F: Fn(&T),
+        F: FnMut(&T),
     {
-        let lock = self.shared.lock().unwrap();
+        let lock = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
 
         let mut next = *lock;
 
@@ -92,7 +97,7 @@ impl<T> Broadcaster<T> {
     }
 
     pub fn listener(&self, value: T) -> Listener<T> {
-        let mut lock = self.shared.lock().unwrap();
+        let mut lock = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
 
         let listener = Box::new(ListenerInternal {
             value,
@@ -115,5 +120,9 @@ impl<T> Broadcaster<T> {
     }
 }
 
+// SAFETY: a listener's links are only accessed while holding the shared lock, and its value may be
+// accessed from any thread that broadcasts.
+unsafe impl<T: Send + Sync> Send for Listener<T> {}
+
 unsafe impl<T> Send for Broadcaster<T> {}
 unsafe impl<T> Sync for Broadcaster<T> {}
@@ -0,0 +1,366 @@
+//! Collections of [VersionedCell]s that are versioned as a whole.
+//!
+//! A `Vec<VersionedCell<'store, T>>` in a store's data graph can only be changed structurally
+//! (elements added, removed or reordered) if it is itself wrapped in a [VersionedCell], and such a
+//! change is then only observable as a change in the version of the cell that wraps it. The
+//! collections in this module make that pattern explicit: they track a "structure version" that
+//! changes whenever the collection changes structurally, separately from the versions of the
+//! individual elements, which change whenever an element is mutably borrowed.
+//!
+//! See [VersionedVecMemo](crate::memo::VersionedVecMemo) and
+//! [VersionedMapMemo](crate::memo::VersionedMapMemo) for memos that distinguish between structural
+//! changes and element changes.
+//!
+//! Structural changes are reverted (when an update scope is rolled back or undone, see
+//! [Store::try_update] and [Store::set_history_depth]) by swapping the collection's cells with a
+//! copy that was made when the structure was first borrowed mutably in the scope. This requires
+//! that the elements (and a map's keys) implement `Clone` and don't own any cells (see
+//! [CellFree]). Otherwise a collection's structure cannot be copied, so changing it inside of
+//! [Store::try_update] panics, and changing it inside of any other update scope clears the store's
+//! undo history.
+//!
+//! [Store::try_update]: crate::store::Store::try_update
+//! [Store::set_history_depth]: crate::store::Store::set_history_depth
+//! [CellFree]: crate::versioned_cell::CellFree
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::snapshot::{CellMap, SnapshotClone, SnapshotToken};
+use crate::store::{ReadContext, UpdateContext};
+use crate::versioned_cell::{Ref, RefMut, VersionedCell};
+
+/// A vector of [VersionedCell]s with a structure version.
+pub struct VersionedVec<'store, T: 'store> {
+    cells: VersionedCell<'store, Vec<VersionedCell<'store, T>>>,
+}
+
+impl<'store, T: 'store> VersionedVec<'store, T> {
+    /// Returns a new empty [VersionedVec].
+    pub fn new(context: UpdateContext<'store>) -> Self {
+        VersionedVec::from_vec(context, Vec::new())
+    }
+
+    /// Returns a new [VersionedVec] that contains a new cell for each of the `values`.
+    pub fn from_vec(context: UpdateContext<'store>, values: Vec<T>) -> Self {
+        let cells = values
+            .into_iter()
+            .map(|value| VersionedCell::new(context, value))
+            .collect();
+
+        VersionedVec {
+            cells: VersionedCell::new(context, cells),
+        }
+    }
+
+    /// The version of the vector's structure.
+    ///
+    /// Changes whenever elements are added, removed or reordered, but not when an element's value
+    /// changes.
+    pub fn structure_version(&self) -> u64 {
+        self.cells.version()
+    }
+
+    /// Returns the element cells inside a read scope.
+    pub fn deref(&self, context: ReadContext<'store>) -> &[VersionedCell<'store, T>] {
+        self.cells.deref(context)
+    }
+
+    /// Returns the number of elements inside a read scope.
+    pub fn len(&self, context: ReadContext<'store>) -> usize {
+        self.deref(context).len()
+    }
+
+    /// Whether the vector is empty inside a read scope.
+    pub fn is_empty(&self, context: ReadContext<'store>) -> bool {
+        self.deref(context).is_empty()
+    }
+
+    /// Returns the cell for the element at the `index` inside a read scope, or `None` if the index
+    /// is out of bounds.
+    pub fn get(
+        &self,
+        index: usize,
+        context: ReadContext<'store>,
+    ) -> Option<&VersionedCell<'store, T>> {
+        self.deref(context).get(index)
+    }
+
+    /// Borrows the element cells inside an update scope.
+    ///
+    /// Mutably borrowing an element cell changes the version of that element, but not the
+    /// vector's structure version.
+    pub fn borrow(&self, context: UpdateContext<'store>) -> Ref<'_, Vec<VersionedCell<'store, T>>> {
+        self.cells.borrow(context)
+    }
+
+    /// Mutably borrows the element cells inside an update scope, which changes the vector's
+    /// structure version.
+    pub fn borrow_mut(
+        &self,
+        context: UpdateContext<'store>,
+    ) -> RefMut<'_, Vec<VersionedCell<'store, T>>> {
+        self.cells.borrow_structure_mut(context)
+    }
+
+    /// Appends a new cell with the given `value`.
+    pub fn push(&self, context: UpdateContext<'store>, value: T) {
+        let cell = VersionedCell::new(context, value);
+
+        self.borrow_mut(context).push(cell);
+    }
+
+    /// Removes the last element and returns its value, or `None` if the vector is empty.
+    ///
+    /// Does not change the structure version if the vector is empty.
+    pub fn pop(&self, context: UpdateContext<'store>) -> Option<T> {
+        if self.borrow(context).is_empty() {
+            return None;
+        }
+
+        self.borrow_mut(context)
+            .pop()
+            .map(VersionedCell::into_inner)
+    }
+
+    /// Inserts a new cell with the given `value` at the `index`, shifting all elements after it to
+    /// the right.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&self, context: UpdateContext<'store>, index: usize, value: T) {
+        let cell = VersionedCell::new(context, value);
+
+        self.borrow_mut(context).insert(index, cell);
+    }
+
+    /// Removes the element at the `index` and returns its value, shifting all elements after it to
+    /// the left.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `index` is out of bounds.
+    pub fn remove(&self, context: UpdateContext<'store>, index: usize) -> T {
+        self.borrow_mut(context).remove(index).into_inner()
+    }
+
+    /// Swaps the elements at indices `a` and `b`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `a` or `b` are out of bounds.
+    pub fn swap(&self, context: UpdateContext<'store>, a: usize, b: usize) {
+        self.borrow_mut(context).swap(a, b);
+    }
+
+    /// Removes all elements.
+    ///
+    /// Does not change the structure version if the vector is already empty.
+    pub fn clear(&self, context: UpdateContext<'store>) {
+        if !self.borrow(context).is_empty() {
+            self.borrow_mut(context).clear();
+        }
+    }
+}
+
+impl<T: SnapshotClone> SnapshotClone for VersionedVec<'_, T> {
+    /// Returns a copy of the vector with the same structure version, in which every element is a
+    /// copy of the corresponding element in this vector.
+    fn snapshot_clone(&self, token: SnapshotToken<'_>) -> Self {
+        VersionedVec {
+            cells: self.cells.snapshot_clone(token),
+        }
+    }
+
+    fn map_cells<'a, 'b>(&'a self, copy: &'b Self, map: &mut CellMap<'a, 'b>) {
+        self.cells.map_cells(&copy.cells, map);
+    }
+}
+
+/// A hash map of [VersionedCell]s with a structure version.
+pub struct VersionedMap<'store, K: 'store, V: 'store> {
+    cells: VersionedCell<'store, HashMap<K, VersionedCell<'store, V>>>,
+}
+
+impl<'store, K, V> VersionedMap<'store, K, V>
+where
+    K: Eq + Hash + 'store,
+    V: 'store,
+{
+    /// Returns a new empty [VersionedMap].
+    pub fn new(context: UpdateContext<'store>) -> Self {
+        VersionedMap {
+            cells: VersionedCell::new(context, HashMap::new()),
+        }
+    }
+
+    /// The version of the map's structure.
+    ///
+    /// Changes whenever entries are added or removed, but not when an entry's value changes.
+    pub fn structure_version(&self) -> u64 {
+        self.cells.version()
+    }
+
+    /// Returns the entry cells inside a read scope.
+    pub fn deref(&self, context: ReadContext<'store>) -> &HashMap<K, VersionedCell<'store, V>> {
+        self.cells.deref(context)
+    }
+
+    /// Returns the number of entries inside a read scope.
+    pub fn len(&self, context: ReadContext<'store>) -> usize {
+        self.deref(context).len()
+    }
+
+    /// Whether the map is empty inside a read scope.
+    pub fn is_empty(&self, context: ReadContext<'store>) -> bool {
+        self.deref(context).is_empty()
+    }
+
+    /// Returns the cell for the entry with the given `key` inside a read scope, or `None` if the
+    /// map does not contain the key.
+    pub fn get<Q>(&self, key: &Q, context: ReadContext<'store>) -> Option<&VersionedCell<'store, V>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.deref(context).get(key)
+    }
+
+    /// Borrows the entry cells inside an update scope.
+    ///
+    /// Mutably borrowing an entry cell changes the version of that entry, but not the map's
+    /// structure version.
+    pub fn borrow(
+        &self,
+        context: UpdateContext<'store>,
+    ) -> Ref<'_, HashMap<K, VersionedCell<'store, V>>> {
+        self.cells.borrow(context)
+    }
+
+    /// Mutably borrows the entry cells inside an update scope, which changes the map's structure
+    /// version.
+    pub fn borrow_mut(
+        &self,
+        context: UpdateContext<'store>,
+    ) -> RefMut<'_, HashMap<K, VersionedCell<'store, V>>> {
+        self.cells.borrow_structure_mut(context)
+    }
+
+    /// Inserts the `value` for the `key`.
+    ///
+    /// If the map already contains the key, the value of the existing entry is replaced and the
+    /// old value is returned; this changes the version of the entry, but not the map's structure
+    /// version. Otherwise, a new entry is added and `None` is returned.
+    pub fn insert(&self, context: UpdateContext<'store>, key: K, value: V) -> Option<V> {
+        if let Some(cell) = self.borrow(context).get(&key) {
+            return Some(std::mem::replace(&mut *cell.borrow_mut(context), value));
+        }
+
+        let cell = VersionedCell::new(context, value);
+
+        self.borrow_mut(context).insert(key, cell);
+
+        None
+    }
+
+    /// Removes the entry for the `key` and returns its value, or `None` if the map does not
+    /// contain the key.
+    ///
+    /// Does not change the structure version if the map does not contain the key.
+    pub fn remove<Q>(&self, context: UpdateContext<'store>, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        if !self.borrow(context).contains_key(key) {
+            return None;
+        }
+
+        self.borrow_mut(context)
+            .remove(key)
+            .map(VersionedCell::into_inner)
+    }
+
+    /// Removes all entries.
+    ///
+    /// Does not change the structure version if the map is already empty.
+    pub fn clear(&self, context: UpdateContext<'store>) {
+        if !self.borrow(context).is_empty() {
+            self.borrow_mut(context).clear();
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: SnapshotClone> SnapshotClone for VersionedMap<'_, K, V> {
+    /// Returns a copy of the map with the same structure version, in which every entry is a copy
+    /// of the corresponding entry in this map.
+    fn snapshot_clone(&self, token: SnapshotToken<'_>) -> Self {
+        VersionedMap {
+            cells: self.cells.snapshot_clone(token),
+        }
+    }
+
+    fn map_cells<'a, 'b>(&'a self, copy: &'b Self, map: &mut CellMap<'a, 'b>) {
+        self.cells.map_cells(&copy.cells, map);
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for VersionedVec<'_, T> {
+    /// Serializes the vector as a sequence of its element values.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.cells.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, 'store, T: serde::Deserialize<'de>> serde::Deserialize<'de> for VersionedVec<'store, T> {
+    /// Deserializes a new vector from a sequence of element values, see the `Deserialize`
+    /// implementation of [VersionedCell].
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Ok(VersionedVec {
+            cells: VersionedCell::deserialize(deserializer)?,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, V> serde::Serialize for VersionedMap<'_, K, V>
+where
+    K: serde::Serialize,
+    V: serde::Serialize,
+{
+    /// Serializes the map as a map of its entry values.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.cells.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, 'store, K, V> serde::Deserialize<'de> for VersionedMap<'store, K, V>
+where
+    K: serde::Deserialize<'de> + Eq + Hash,
+    V: serde::Deserialize<'de>,
+{
+    /// Deserializes a new map from a map of entry values, see the `Deserialize` implementation of
+    /// [VersionedCell].
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Ok(VersionedMap {
+            cells: VersionedCell::deserialize(deserializer)?,
+        })
+    }
+}
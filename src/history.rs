@@ -0,0 +1,236 @@
+use std::collections::VecDeque;
+
+use crate::journal::Journal;
+use crate::snapshot::CellMap;
+
+/// Undo/redo history of a store.
+///
+/// Every update scope that changes the store while the history is enabled becomes an undo entry,
+/// in the form of a journal of the state the cells it modified were in before the scope ran.
+/// Undoing an entry reverts that journal, which produces a journal of the state the cells were in
+/// before the undo; that journal becomes a redo entry (and vice versa).
+pub(crate) struct History {
+    depth: usize,
+    undo_stack: VecDeque<Journal>,
+    redo_stack: Vec<Journal>,
+    group_depth: usize,
+    group_open: bool,
+}
+
+impl History {
+    pub(crate) fn new() -> Self {
+        History {
+            depth: 0,
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            group_depth: 0,
+            group_open: false,
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.depth > 0
+    }
+
+    pub(crate) fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+
+        while self.undo_stack.len() > depth {
+            self.undo_stack.pop_front();
+        }
+
+        if depth == 0 {
+            self.clear();
+        }
+    }
+
+    pub(crate) fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub(crate) fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.group_open = false;
+    }
+
+    /// Points every entry at the copies of its cells in the `map`, see [Journal::rebase].
+    ///
+    /// Cells that an entry took out of the store's data (see [Journal::record_structure]) are not
+    /// part of the data, and stay where they are. If an entry refers to any other cell that is not
+    /// in the `map`, the entire history is cleared.
+    pub(crate) fn rebase(&mut self, map: &mut CellMap) {
+        for journal in self.undo_stack.iter().chain(&self.redo_stack) {
+            journal.for_each_owned_cell(&mut |address| map.keep(address));
+        }
+
+        let rebased = self
+            .undo_stack
+            .iter_mut()
+            .chain(self.redo_stack.iter_mut())
+            .all(|journal| journal.rebase(map));
+
+        if !rebased {
+            self.clear();
+        }
+    }
+
+    pub(crate) fn begin_group(&mut self) {
+        if self.group_depth == 0 {
+            self.group_open = false;
+        }
+
+        self.group_depth += 1;
+    }
+
+    pub(crate) fn end_group(&mut self) {
+        self.group_depth -= 1;
+
+        if self.group_depth == 0 {
+            self.group_open = false;
+        }
+    }
+
+    /// Records the journal of an update scope that changed the store as a new undo entry.
+    ///
+    /// If the journal is not restorable, the entire history is cleared instead: the entries that
+    /// were recorded earlier may refer to cells that the scope moved or dropped.
+    pub(crate) fn record(&mut self, journal: Journal) {
+        if !journal.is_restorable() {
+            self.clear();
+
+            return;
+        }
+
+        if journal.is_empty() {
+            return;
+        }
+
+        self.redo_stack.clear();
+
+        if self.group_open {
+            if let Some(entry) = self.undo_stack.back_mut() {
+                entry.append(journal);
+
+                return;
+            }
+        }
+
+        self.undo_stack.push_back(journal);
+        self.group_open = self.group_depth > 0;
+
+        if self.undo_stack.len() > self.depth {
+            self.undo_stack.pop_front();
+        }
+    }
+
+    pub(crate) fn pop_undo(&mut self) -> Option<Journal> {
+        self.group_open = false;
+
+        self.undo_stack.pop_back()
+    }
+
+    pub(crate) fn push_undo(&mut self, journal: Journal) {
+        self.undo_stack.push_back(journal);
+    }
+
+    pub(crate) fn pop_redo(&mut self) -> Option<Journal> {
+        self.group_open = false;
+
+        self.redo_stack.pop()
+    }
+
+    pub(crate) fn push_redo(&mut self, journal: Journal) {
+        self.redo_stack.push(journal);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collections::VersionedVec;
+    use crate::store::Store;
+    use crate::versioned_cell::VersionedCell;
+
+    struct Root<'store> {
+        a: VersionedCell<'store, String>,
+        b: VersionedVec<'store, u32>,
+    }
+
+    crate::gen_type_constructor!(Root, RootTC);
+
+    fn store() -> Store<RootTC> {
+        let store = Store::<RootTC>::initialize(|cx| Root {
+            a: VersionedCell::new(cx, String::from("a")),
+            b: VersionedVec::new(cx),
+        });
+
+        store.set_history_depth(10);
+
+        store
+    }
+
+    #[test]
+    fn undo_skips_cells_created_in_scope() {
+        let store = store();
+
+        store.update(|root, cx| {
+            root.a.borrow_mut(cx).push('b');
+
+            let local = [VersionedCell::new(cx, String::from("local"))];
+
+            local[0].borrow_mut(cx).push('c');
+        });
+
+        assert!(store.undo());
+        assert_eq!(store.with(|root, cx| root.a.deref(cx).clone()), "a");
+
+        assert!(store.redo());
+        assert_eq!(store.with(|root, cx| root.a.deref(cx).clone()), "ab");
+    }
+
+    #[test]
+    fn structural_change_can_be_undone() {
+        let store = store();
+        let values = |store: &Store<RootTC>| {
+            store.with(|root, cx| {
+                (root.b.deref(cx).iter())
+                    .map(|cell| *cell.deref(cx))
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        store.update(|root, cx| root.b.push(cx, 1));
+
+        store.update(|root, cx| {
+            *root.b.borrow(cx)[0].borrow_mut(cx) = 2;
+            root.b.push(cx, 3);
+            *root.b.borrow(cx)[1].borrow_mut(cx) = 4;
+        });
+
+        store.update(|root, cx| {
+            root.b.remove(cx, 0);
+        });
+
+        assert_eq!(values(&store), [4]);
+
+        assert!(store.undo());
+        assert_eq!(values(&store), [2, 4]);
+
+        assert!(store.undo());
+        assert_eq!(values(&store), [1]);
+
+        assert!(store.undo());
+        assert!(values(&store).is_empty());
+
+        assert!(store.redo());
+        assert!(store.redo());
+        assert_eq!(values(&store), [2, 4]);
+
+        assert!(store.redo());
+        assert_eq!(values(&store), [4]);
+    }
+}
@@ -0,0 +1,618 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::mem;
+
+use crate::snapshot::CellMap;
+use crate::store::UpdateContext;
+use crate::versioned_cell::{CellFree, VersionedCell};
+
+// A journal records the state of every `VersionedCell` that is modified during an update scope, so
+// that the scope can later be rolled back. Cells are recorded the first time they are touched or
+// mutably borrowed; restoring the recorded states in reverse order returns every cell to the state
+// it was in when the scope began.
+//
+// Restoring a cell's value requires a copy of that value, which we can only make if the value
+// implements `Clone`. Additionally, we only copy values that do not own other cells (see
+// `CellFree`): restoring such a value would replace the cells it owns with copies at different
+// addresses, invalidating any pointers to those cells recorded earlier. Journals are only used for
+// stores whose data is `CellStable`, in which cells are only moved or dropped when the value of a
+// cell that owns them is mutably borrowed (and not e.g. through a `Mutex` that owns cells).
+//
+// The values of the cells that own the elements of the collections in `crate::collections` (see
+// `Structure`) are recorded the other way around: the journal takes the value itself, which leaves
+// the element cells at their addresses, and replaces it with a copy that has copies of the element
+// cells. Restoring the value then puts the original element cells back in place. This requires the
+// elements' values to be copied, so it is again limited to values that implement `Clone` and do
+// not own other cells.
+//
+// A strict journal does not allow mutably borrowing cells whose values cannot be recorded; a
+// lenient journal allows it, but then marks itself as no longer restorable. This ensures that the
+// cell pointers recorded in a restorable journal remain valid until the journal is restored: they
+// either point into the store's data, or at cells that a journal took out of the store's data.
+//
+// Cells that were created after the journal was started are never recorded: they did not exist
+// when the scope began, so there is no state to restore them to. Such cells need not be part of
+// the store's data (e.g. a cell in a local variable inside the scope), and may be dropped before
+// the journal is restored. A cell's version is assigned when it is created and only increases
+// afterwards, so a cell whose version is at least the store's version at the start of the journal
+// was created after the journal was started, unless the journal already recorded its version
+// before it was touched.
+
+pub(crate) struct Journal {
+    entries: Vec<Box<dyn Entry>>,
+    recorded_values: HashSet<usize>,
+    recorded_versions: HashSet<usize>,
+    // The number of entries after each structure was last recorded, see `record_structure`.
+    recorded_structures: HashMap<usize, usize>,
+    start_version: u64,
+    strict: bool,
+    restorable: bool,
+}
+
+impl Journal {
+    /// Returns a new journal that panics when asked to record a value that cannot be recorded.
+    ///
+    /// The `start_version` must be the store's version when the journal is started.
+    pub(crate) fn strict(start_version: u64) -> Self {
+        Journal::new(true, start_version)
+    }
+
+    /// Returns a new journal that becomes unrestorable when asked to record a value that cannot
+    /// be recorded.
+    ///
+    /// The `start_version` must be the store's version when the journal is started.
+    pub(crate) fn lenient(start_version: u64) -> Self {
+        Journal::new(false, start_version)
+    }
+
+    fn new(strict: bool, start_version: u64) -> Self {
+        Journal {
+            entries: Vec::new(),
+            recorded_values: HashSet::new(),
+            recorded_versions: HashSet::new(),
+            recorded_structures: HashMap::new(),
+            start_version,
+            strict,
+            restorable: true,
+        }
+    }
+
+    /// Whether the journal recorded any cells.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether the journal can be restored, see [Journal::lenient].
+    pub(crate) fn is_restorable(&self) -> bool {
+        self.restorable
+    }
+
+    /// Removes all recorded state from the journal and returns it as a new journal.
+    pub(crate) fn take(&mut self) -> Journal {
+        mem::replace(self, Journal::new(self.strict, self.start_version))
+    }
+
+    /// Appends the state recorded in `other` to this journal, so that restoring this journal also
+    /// restores `other` (before restoring the state that was already recorded in this journal).
+    pub(crate) fn append(&mut self, other: Journal) {
+        let offset = self.entries.len();
+
+        self.recorded_structures.extend(
+            other
+                .recorded_structures
+                .into_iter()
+                .map(|(address, len)| (address, offset + len)),
+        );
+        self.entries.extend(other.entries);
+        self.recorded_values.extend(other.recorded_values);
+        self.recorded_versions.extend(other.recorded_versions);
+        self.restorable &= other.restorable;
+    }
+
+    /// Records the value and version of the `cell`, if they were not already recorded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the journal is strict and the cell's value cannot be recorded.
+    pub(crate) fn record_value<'store, T: 'store>(&mut self, cell: &VersionedCell<'store, T>) {
+        let address = cell as *const VersionedCell<'store, T> as usize;
+
+        if !self.restorable
+            || self.is_created_after_start(cell, address)
+            || !self.recorded_values.insert(address)
+        {
+            return;
+        }
+
+        // SAFETY: recording happens before the cell is mutably borrowed, so there are no live
+        // mutable references to the value.
+        let Some(value) = self.recordable(unsafe { cell.value_unchecked() }.try_clone()) else {
+            return;
+        };
+
+        let entry: Box<dyn Entry + 'store> = Box::new(CellState {
+            cell,
+            version: cell.version(),
+            value: Some(value),
+        });
+
+        // SAFETY: the entry is only ever restored or dropped while the store's data is alive, see
+        // the note at the top of this module.
+        self.entries.push(unsafe { erase_lifetime(entry) });
+    }
+
+    /// Records the value and version of the `cell`, whose value owns the cells of a collection.
+    ///
+    /// Replaces the cell's value with a copy, and records the original value, so that the cells
+    /// it owns stay where they are (see the note at the top of this module). The value is only
+    /// replaced again if the journal recorded other cells since, which may be cells of the copy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the journal is strict and the cell's value cannot be recorded.
+    pub(crate) fn record_structure<'store, S>(&mut self, cell: &VersionedCell<'store, S>)
+    where
+        S: Structure + 'store,
+    {
+        let address = cell as *const VersionedCell<'store, S> as usize;
+
+        if !self.restorable
+            || self.recorded_structures.get(&address) == Some(&self.entries.len())
+            || (!self.recorded_structures.contains_key(&address)
+                && self.is_created_after_start(cell, address))
+        {
+            return;
+        }
+
+        // SAFETY: recording happens before the cell is mutably borrowed, so there are no live
+        // references to the value, or to the element cells it owns.
+        let Some(copy) = self.recordable(unsafe { cell.value_unchecked().try_copy() }) else {
+            return;
+        };
+
+        let entry: Box<dyn Entry + 'store> = Box::new(StructureState {
+            cell,
+            version: cell.version(),
+            // SAFETY: see above.
+            value: unsafe { cell.replace_unchecked(copy) },
+        });
+
+        // SAFETY: see `record_value`.
+        self.entries.push(unsafe { erase_lifetime(entry) });
+        self.recorded_values.insert(address);
+        self.recorded_structures.insert(address, self.entries.len());
+    }
+
+    /// Returns the `copy` of the value of a cell that is about to be recorded.
+    ///
+    /// If the value could not be copied, marks a lenient journal as no longer restorable.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the journal is strict and the value could not be copied.
+    fn recordable<T>(&mut self, copy: Option<T>) -> Option<T> {
+        if copy.is_none() {
+            if self.strict {
+                panic!(
+                    "cannot mutably borrow a `VersionedCell` whose value does not implement \
+                    `Clone` or that owns other `VersionedCell`s inside a scope that may be rolled \
+                    back"
+                );
+            }
+
+            self.restorable = false;
+        }
+
+        copy
+    }
+
+    /// Records the version of the `cell`, if its state was not already recorded.
+    pub(crate) fn record_version<'store, T: 'store>(&mut self, cell: &VersionedCell<'store, T>) {
+        let address = cell as *const VersionedCell<'store, T> as usize;
+
+        if !self.restorable
+            || self.is_created_after_start(cell, address)
+            || self.recorded_values.contains(&address)
+            || !self.recorded_versions.insert(address)
+        {
+            return;
+        }
+
+        let entry: Box<dyn Entry + 'store> = Box::new(CellState {
+            cell,
+            version: cell.version(),
+            value: None,
+        });
+
+        // SAFETY: see `record_value`.
+        self.entries.push(unsafe { erase_lifetime(entry) });
+    }
+
+    /// Whether the `cell` at the `address` was created after the journal was started, see the note
+    /// at the top of this module.
+    fn is_created_after_start<T>(&self, cell: &VersionedCell<T>, address: usize) -> bool {
+        cell.version() >= self.start_version && !self.recorded_versions.contains(&address)
+    }
+
+    /// Points the journal at the copies of the recorded cells in the `map`, after the store's data
+    /// was replaced with a copy (see `Store::snapshot`).
+    ///
+    /// Returns `false` if a recorded cell is not in the `map`, in which case the journal must no
+    /// longer be restored.
+    pub(crate) fn rebase(&mut self, map: &CellMap) -> bool {
+        let rebase = |addresses: &HashSet<usize>| {
+            addresses
+                .iter()
+                .map(|address| map.get(*address))
+                .collect::<Option<HashSet<_>>>()
+        };
+
+        let structures = self
+            .recorded_structures
+            .iter()
+            .map(|(address, len)| Some((map.get(*address)?, *len)))
+            .collect::<Option<HashMap<_, _>>>();
+
+        match (
+            rebase(&self.recorded_values),
+            rebase(&self.recorded_versions),
+            structures,
+        ) {
+            (Some(values), Some(versions), Some(structures)) => {
+                self.recorded_values = values;
+                self.recorded_versions = versions;
+                self.recorded_structures = structures;
+            }
+            _ => return false,
+        }
+
+        self.entries.iter_mut().all(|entry| entry.rebase(map))
+    }
+
+    /// Calls `f` with the address of every cell that the journal took out of the store's data (see
+    /// [Journal::record_structure]).
+    pub(crate) fn for_each_owned_cell(&self, f: &mut dyn FnMut(usize)) {
+        for entry in &self.entries {
+            entry.for_each_owned_cell(f);
+        }
+    }
+
+    /// Restores all recorded cells to their recorded state.
+    ///
+    /// # Safety
+    ///
+    /// Must be called inside the update scope of the store that owns the recorded cells, while none
+    /// of the recorded cells are borrowed. The journal must be restorable.
+    pub(crate) unsafe fn rollback(self) {
+        debug_assert!(self.restorable);
+
+        for entry in self.entries.into_iter().rev() {
+            entry.restore();
+        }
+    }
+
+    /// Restores all recorded cells to their recorded value, but assigns each cell a new version.
+    ///
+    /// Returns a journal that records the state of the cells before they were reverted, which may
+    /// in turn be reverted to undo the revert.
+    ///
+    /// # Safety
+    ///
+    /// The `context` must belong to the update scope of the store that owns the recorded cells,
+    /// and none of the recorded cells may be borrowed. The journal must be restorable.
+    pub(crate) unsafe fn revert(self, context: UpdateContext) -> Journal {
+        debug_assert!(self.restorable);
+
+        let mut inverse = Journal::new(self.strict, context.store_version());
+
+        for entry in self.entries.into_iter().rev() {
+            entry.revert(&mut inverse, context.next_version());
+        }
+
+        inverse
+    }
+}
+
+// SAFETY: a journal is only accessed by the store that owns it while holding the store's write
+// lock. It only holds pointers to cells in the store's data and values cloned from those cells,
+// which are shared between threads along with the store.
+unsafe impl Send for Journal {}
+unsafe impl Sync for Journal {}
+
+unsafe fn erase_lifetime<'store>(entry: Box<dyn Entry + 'store>) -> Box<dyn Entry> {
+    mem::transmute::<Box<dyn Entry + 'store>, Box<dyn Entry>>(entry)
+}
+
+trait Entry {
+    unsafe fn restore(self: Box<Self>);
+
+    unsafe fn revert(self: Box<Self>, inverse: &mut Journal, version: u64);
+
+    fn rebase(&mut self, map: &CellMap) -> bool;
+
+    fn for_each_owned_cell(&self, _f: &mut dyn FnMut(usize)) {}
+}
+
+struct CellState<'store, T> {
+    cell: *const VersionedCell<'store, T>,
+    version: u64,
+    value: Option<T>,
+}
+
+impl<'store, T> Entry for CellState<'store, T> {
+    unsafe fn restore(self: Box<Self>) {
+        let CellState {
+            cell,
+            version,
+            value,
+        } = *self;
+
+        (*cell).restore_unchecked(version, value);
+    }
+
+    unsafe fn revert(self: Box<Self>, inverse: &mut Journal, version: u64) {
+        let CellState { cell, value, .. } = *self;
+
+        if value.is_some() {
+            inverse.record_value(&*cell);
+        } else {
+            inverse.record_version(&*cell);
+        }
+
+        (*cell).restore_unchecked(version, value);
+    }
+
+    fn rebase(&mut self, map: &CellMap) -> bool {
+        match map.get(self.cell as usize) {
+            Some(address) => {
+                self.cell = address as *const VersionedCell<'store, T>;
+
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+struct StructureState<'store, S> {
+    cell: *const VersionedCell<'store, S>,
+    version: u64,
+    value: S,
+}
+
+impl<'store, S: Structure> Entry for StructureState<'store, S> {
+    unsafe fn restore(self: Box<Self>) {
+        let StructureState {
+            cell,
+            version,
+            value,
+        } = *self;
+
+        (*cell).restore_unchecked(version, Some(value));
+    }
+
+    unsafe fn revert(self: Box<Self>, inverse: &mut Journal, version: u64) {
+        let StructureState { cell, value, .. } = *self;
+
+        // Swap the values rather than recording a copy, so that the inverse journal takes the
+        // cells that are currently in place.
+        let entry: Box<dyn Entry + 'store> = Box::new(StructureState {
+            cell,
+            version: (*cell).version(),
+            value: (*cell).replace_unchecked(value),
+        });
+
+        inverse.entries.push(erase_lifetime(entry));
+        inverse.recorded_values.insert(cell as usize);
+        inverse
+            .recorded_structures
+            .insert(cell as usize, inverse.entries.len());
+
+        (*cell).restore_unchecked(version, None);
+    }
+
+    fn rebase(&mut self, map: &CellMap) -> bool {
+        match map.get(self.cell as usize) {
+            Some(address) => {
+                self.cell = address as *const VersionedCell<'store, S>;
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn for_each_owned_cell(&self, f: &mut dyn FnMut(usize)) {
+        self.value.for_each_cell(f);
+    }
+}
+
+/// The value of a cell that owns the element cells of a collection, see
+/// [Journal::record_structure].
+pub(crate) trait Structure: Sized {
+    /// Returns a copy of the value with copies of the element cells (with the same versions), or
+    /// `None` if the elements' values cannot be cloned (see [TryClone]).
+    ///
+    /// # Safety
+    ///
+    /// None of the element cells may be mutably borrowed.
+    unsafe fn try_copy(&self) -> Option<Self>;
+
+    /// Calls `f` with the address of every element cell.
+    fn for_each_cell(&self, f: &mut dyn FnMut(usize));
+}
+
+impl<'store, T> Structure for Vec<VersionedCell<'store, T>> {
+    unsafe fn try_copy(&self) -> Option<Self> {
+        self.iter().map(|cell| cell.try_clone_unchecked()).collect()
+    }
+
+    fn for_each_cell(&self, f: &mut dyn FnMut(usize)) {
+        for cell in self {
+            f(cell as *const VersionedCell<'store, T> as usize);
+        }
+    }
+}
+
+impl<'store, K: Eq + Hash, V> Structure for HashMap<K, VersionedCell<'store, V>> {
+    unsafe fn try_copy(&self) -> Option<Self> {
+        let mut copy = HashMap::with_capacity_and_hasher(self.len(), self.hasher().clone());
+
+        for (key, cell) in self {
+            copy.insert(key.try_clone()?, cell.try_clone_unchecked()?);
+        }
+
+        Some(copy)
+    }
+
+    fn for_each_cell(&self, f: &mut dyn FnMut(usize)) {
+        for cell in self.values() {
+            f(cell as *const VersionedCell<'store, V> as usize);
+        }
+    }
+}
+
+// Whether a value can be recorded is decided at the point where its cell is mutably borrowed,
+// which has no `Clone` bound, so this relies on specialization. `min_specialization` is not enough
+// here, as it does not allow specializing on `Clone`.
+pub(crate) trait TryClone: Sized {
+    fn try_clone(&self) -> Option<Self>;
+}
+
+impl<T> TryClone for T {
+    default fn try_clone(&self) -> Option<Self> {
+        None
+    }
+}
+
+impl<T: Clone + CellFree> TryClone for T {
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::collections::{VersionedMap, VersionedVec};
+    use crate::store::Store;
+    use crate::versioned_cell::VersionedCell;
+
+    struct Root<'store> {
+        a: VersionedCell<'store, String>,
+        b: VersionedVec<'store, String>,
+        c: VersionedMap<'store, u32, String>,
+    }
+
+    crate::gen_type_constructor!(Root, RootTC);
+
+    struct MutexRoot<'store> {
+        a: VersionedCell<'store, String>,
+        count: Mutex<u32>,
+    }
+
+    crate::gen_type_constructor!(MutexRoot, MutexRootTC);
+
+    fn store() -> Store<RootTC> {
+        Store::initialize(|cx| Root {
+            a: VersionedCell::new(cx, String::from("a")),
+            b: VersionedVec::from_vec(cx, vec![String::from("b")]),
+            c: VersionedMap::new(cx),
+        })
+    }
+
+    fn vec_values(store: &Store<RootTC>) -> Vec<String> {
+        store.with(|root, cx| {
+            root.b
+                .deref(cx)
+                .iter()
+                .map(|cell| cell.deref(cx).clone())
+                .collect()
+        })
+    }
+
+    #[test]
+    fn rollback_skips_cells_created_in_scope() {
+        let store = store();
+
+        let result: Result<(), ()> = store.try_update(|root, cx| {
+            root.a.borrow_mut(cx).push('b');
+
+            let local = vec![VersionedCell::new(cx, String::from("local"))];
+
+            local[0].borrow_mut(cx).push('c');
+            local[0].touch(cx);
+
+            drop(local);
+
+            Err(())
+        });
+
+        assert_eq!(result, Err(()));
+        assert_eq!(store.with(|root, cx| root.a.deref(cx).clone()), "a");
+    }
+
+    #[test]
+    fn rollback_restores_cells_touched_before_borrow() {
+        let store = store();
+
+        let result: Result<(), ()> = store.try_update(|root, cx| {
+            root.a.touch(cx);
+            root.a.borrow_mut(cx).push('b');
+
+            Err(())
+        });
+
+        assert_eq!(result, Err(()));
+        assert_eq!(store.with(|root, cx| root.a.deref(cx).clone()), "a");
+    }
+
+    #[test]
+    fn rollback_with_cell_free_interior_mutability() {
+        let store = Store::<MutexRootTC>::initialize(|cx| MutexRoot {
+            a: VersionedCell::new(cx, String::from("a")),
+            count: Mutex::new(0),
+        });
+
+        let result: Result<(), ()> = store.try_update(|root, cx| {
+            root.a.borrow_mut(cx).push('b');
+            *root.count.lock().unwrap() += 1;
+
+            Err(())
+        });
+
+        assert_eq!(result, Err(()));
+        assert_eq!(store.with(|root, cx| root.a.deref(cx).clone()), "a");
+        assert_eq!(store.with(|root, _| *root.count.lock().unwrap()), 1);
+    }
+
+    #[test]
+    fn rollback_restores_collection_structure() {
+        let store = store();
+        let version = store.with(|root, _| root.b.structure_version());
+
+        store.update(|root, cx| root.c.insert(cx, 1, String::from("c")));
+
+        let result: Result<(), ()> = store.try_update(|root, cx| {
+            root.b.push(cx, String::from("d"));
+            root.b.borrow(cx)[0].borrow_mut(cx).push('e');
+            root.b.remove(cx, 0);
+            root.b.borrow(cx)[0].borrow_mut(cx).push('f');
+
+            root.c.remove(cx, &1);
+            root.c.insert(cx, 2, String::from("g"));
+
+            Err(())
+        });
+
+        assert_eq!(result, Err(()));
+        assert_eq!(vec_values(&store), ["b"]);
+        assert_eq!(store.with(|root, _| root.b.structure_version()), version);
+        assert_eq!(
+            store.with(|root, cx| root.c.get(&1, cx).map(|cell| cell.deref(cx).clone())),
+            Some(String::from("c"))
+        );
+        assert_eq!(store.with(|root, cx| root.c.len(cx)), 1);
+    }
+}
@@ -1,11 +1,30 @@
-#![feature(generic_associated_types, associated_type_defaults)]
+#![feature(
+    generic_associated_types,
+    associated_type_defaults,
+    specialization,
+    auto_traits,
+    negative_impls
+)]
+#![allow(incomplete_features)]
 
 mod broadcast;
 
+mod history;
+
+mod journal;
+
+mod tracker;
+
 mod type_constructor;
 pub use self::type_constructor::TypeConstructor;
 
+#[cfg(feature = "derive")]
+pub use viemo_derive::{Selectors, SnapshotClone, TypeConstructor};
+
+pub mod collections;
 pub mod memo;
+pub mod select;
+pub mod snapshot;
 pub mod store;
 pub mod versioned_cell;
 pub mod watcher;
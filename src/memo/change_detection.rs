@@ -0,0 +1,199 @@
+use std::hash::{Hash, Hasher};
+
+use seahash::SeaHasher;
+
+/// How a memo for a slice (or another sequence) of cells detects changes to the versions of those
+/// cells.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ChangeDetection {
+    /// Compares a 64-bit digest of the cell versions.
+    ///
+    /// Uses a constant amount of memory regardless of the number of cells, but a digest
+    /// collision can (with very low probability) cause a change to go unreported.
+    #[default]
+    Hashed,
+    /// Stores the version of every cell and compares them exactly.
+    ///
+    /// Never misses a change, at the cost of memory proportional to the number of cells.
+    Exact,
+}
+
+/// The cell versions of a slice as recorded by a slice memo, see [ChangeDetection].
+pub(crate) enum SliceVersions {
+    Hashed(u64),
+    Exact(Vec<u64>),
+}
+
+impl SliceVersions {
+    pub(crate) fn new(
+        change_detection: ChangeDetection,
+        versions: impl Iterator<Item = u64>,
+    ) -> Self {
+        match change_detection {
+            ChangeDetection::Hashed => SliceVersions::Hashed(hash_versions(versions)),
+            ChangeDetection::Exact => SliceVersions::Exact(versions.collect()),
+        }
+    }
+
+    /// Replaces the recorded versions with the `versions` and returns whether they differ.
+    pub(crate) fn update(&mut self, versions: impl Iterator<Item = u64>) -> bool {
+        match self {
+            SliceVersions::Hashed(last) => {
+                let version = hash_versions(versions);
+                let is_changed = version != *last;
+
+                *last = version;
+
+                is_changed
+            }
+            SliceVersions::Exact(last) => {
+                let mut is_changed = false;
+                let mut len = 0;
+
+                for version in versions {
+                    if let Some(last_version) = last.get_mut(len) {
+                        if *last_version != version {
+                            *last_version = version;
+                            is_changed = true;
+                        }
+                    } else {
+                        last.push(version);
+                        is_changed = true;
+                    }
+
+                    len += 1;
+                }
+
+                if len < last.len() {
+                    last.truncate(len);
+                    is_changed = true;
+                }
+
+                is_changed
+            }
+        }
+    }
+}
+
+/// Like [SliceVersions], but for a slice that may not be present.
+pub(crate) struct OptionSliceVersions {
+    change_detection: ChangeDetection,
+    versions: Option<SliceVersions>,
+}
+
+impl OptionSliceVersions {
+    pub(crate) fn new(
+        change_detection: ChangeDetection,
+        versions: Option<impl Iterator<Item = u64>>,
+    ) -> Self {
+        OptionSliceVersions {
+            change_detection,
+            versions: versions.map(|versions| SliceVersions::new(change_detection, versions)),
+        }
+    }
+
+    /// Replaces the recorded versions with the `versions` and returns whether they differ.
+    pub(crate) fn update(&mut self, versions: Option<impl Iterator<Item = u64>>) -> bool {
+        match (&mut self.versions, versions) {
+            (Some(last), Some(versions)) => last.update(versions),
+            (None, None) => false,
+            (last, versions) => {
+                *last =
+                    versions.map(|versions| SliceVersions::new(self.change_detection, versions));
+
+                true
+            }
+        }
+    }
+}
+
+fn hash_versions(versions: impl Iterator<Item = u64>) -> u64 {
+    let mut hasher = SeaHasher::new();
+
+    for version in versions {
+        version.hash(&mut hasher);
+    }
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use super::{ChangeDetection, OptionSliceVersions, SliceVersions};
+
+    const NONE: Option<iter::Empty<u64>> = None;
+
+    fn versions(versions: &[u64]) -> impl Iterator<Item = u64> + '_ {
+        versions.iter().copied()
+    }
+
+    fn exact(last: &SliceVersions) -> &[u64] {
+        match last {
+            SliceVersions::Exact(last) => last,
+            SliceVersions::Hashed(_) => panic!("expected exact versions"),
+        }
+    }
+
+    #[test]
+    fn exact_update_equal_length() {
+        let mut last = SliceVersions::new(ChangeDetection::Exact, versions(&[1, 2, 3]));
+
+        assert!(!last.update(versions(&[1, 2, 3])));
+        assert!(last.update(versions(&[1, 4, 3])));
+        assert_eq!(exact(&last), [1, 4, 3]);
+        assert!(!last.update(versions(&[1, 4, 3])));
+    }
+
+    #[test]
+    fn exact_update_grow() {
+        let mut last = SliceVersions::new(ChangeDetection::Exact, versions(&[1, 2]));
+
+        assert!(last.update(versions(&[1, 2, 3])));
+        assert_eq!(exact(&last), [1, 2, 3]);
+
+        assert!(SliceVersions::new(ChangeDetection::Exact, versions(&[])).update(versions(&[1])));
+    }
+
+    #[test]
+    fn exact_update_shrink() {
+        let mut last = SliceVersions::new(ChangeDetection::Exact, versions(&[1, 2, 3]));
+
+        assert!(last.update(versions(&[1, 2])));
+        assert_eq!(exact(&last), [1, 2]);
+
+        assert!(last.update(versions(&[])));
+        assert!(exact(&last).is_empty());
+        assert!(!last.update(versions(&[])));
+    }
+
+    #[test]
+    fn hashed_update() {
+        let mut last = SliceVersions::new(ChangeDetection::Hashed, versions(&[1, 2, 3]));
+
+        assert!(!last.update(versions(&[1, 2, 3])));
+        assert!(last.update(versions(&[1, 2])));
+        assert!(last.update(versions(&[2, 1])));
+        assert!(!last.update(versions(&[2, 1])));
+    }
+
+    #[test]
+    fn option_update_some_and_none() {
+        for change_detection in [ChangeDetection::Hashed, ChangeDetection::Exact] {
+            let mut last = OptionSliceVersions::new(change_detection, Some(versions(&[1, 2])));
+
+            assert!(!last.update(Some(versions(&[1, 2]))));
+            assert!(last.update(NONE));
+            assert!(!last.update(NONE));
+
+            // An empty slice is different from no slice.
+            assert!(last.update(Some(versions(&[]))));
+            assert!(last.update(NONE));
+
+            assert!(last.update(Some(versions(&[1, 2]))));
+            assert!(!last.update(Some(versions(&[1, 2]))));
+            assert!(last.update(Some(versions(&[1, 3]))));
+        }
+    }
+}
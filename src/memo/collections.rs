@@ -0,0 +1,236 @@
+use std::hash::Hash;
+use std::marker;
+
+use crate::collections::{VersionedMap, VersionedVec};
+use crate::memo::{ChangeDetection, Memo, MemoLifetime, Refresh, SliceVersions};
+use crate::store::{ReadContext, Store};
+use crate::versioned_cell::VersionedCell;
+use crate::TypeConstructor;
+
+/// The value of a [VersionedVecMemo] or [VersionedMapMemo].
+pub struct CollectionChange<'b, X> {
+    /// The selected collection.
+    pub collection: &'b X,
+    /// Whether the collection's structure changed, see e.g. [VersionedVec::structure_version].
+    pub is_structure_changed: bool,
+    /// Whether the version of any element changed while the structure stayed the same.
+    ///
+    /// Always `false` if the structure changed, in which case any element may have changed.
+    pub is_element_changed: bool,
+}
+
+/// Memo for a [VersionedVec] that distinguishes between changes to the vector's structure and
+/// changes to its elements.
+pub struct VersionedVecMemo<C, S> {
+    selector: S,
+    store_id: usize,
+    versions: CollectionVersions,
+    _marker: marker::PhantomData<*const C>,
+}
+
+impl<C, S, T: 'static> VersionedVecMemo<C, S>
+where
+    C: TypeConstructor,
+    S: for<'a, 'store> Fn(&'a C::Type<'store>, ReadContext<'store>) -> &'a VersionedVec<'store, T>,
+{
+    pub fn new(store: &Store<C>, selector: S) -> Self {
+        Self::with_change_detection(store, selector, ChangeDetection::Hashed)
+    }
+
+    /// Returns a new memo that detects changes to the elements of the selected vector with the
+    /// given `change_detection` mode.
+    pub fn with_change_detection(
+        store: &Store<C>,
+        selector: S,
+        change_detection: ChangeDetection,
+    ) -> Self {
+        let versions = store.with(|root, cx| {
+            let vec = selector(root, cx);
+
+            CollectionVersions::new(change_detection, vec.structure_version(), vec.deref(cx))
+        });
+
+        VersionedVecMemo {
+            selector,
+            store_id: store.id(),
+            versions,
+            _marker: marker::PhantomData,
+        }
+    }
+}
+
+impl<'a, 'b, 'store, C, S, T: 'static> MemoLifetime<'a, 'b, 'store> for VersionedVecMemo<C, S>
+where
+    C: TypeConstructor + 'static,
+    S: Fn(&'b C::Type<'store>, ReadContext<'store>) -> &'b VersionedVec<'store, T> + 'static,
+{
+    type Value = CollectionChange<'b, VersionedVec<'store, T>>;
+}
+
+impl<C, S, T: 'static> Memo for VersionedVecMemo<C, S>
+where
+    C: TypeConstructor + 'static,
+    S: for<'a, 'store> Fn(&'a C::Type<'store>, ReadContext<'store>) -> &'a VersionedVec<'store, T>
+        + 'static,
+{
+    type RootTC = C;
+
+    fn store_id(&self) -> usize {
+        self.store_id
+    }
+
+    fn refresh_unchecked<'a, 'b, 'store: 'b>(
+        &'a mut self,
+        root: &'b C::Type<'store>,
+        cx: ReadContext<'store>,
+    ) -> Refresh<<Self as MemoLifetime<'a, 'b, 'store>>::Value> {
+        let vec = (self.selector)(root, cx);
+        let change = self.versions.update(vec.structure_version(), vec.deref(cx));
+
+        change.with_collection(vec)
+    }
+}
+
+/// Memo for a [VersionedMap] that distinguishes between changes to the map's structure and
+/// changes to its entries.
+pub struct VersionedMapMemo<C, S> {
+    selector: S,
+    store_id: usize,
+    versions: CollectionVersions,
+    _marker: marker::PhantomData<*const C>,
+}
+
+impl<C, S, K: Eq + Hash + 'static, V: 'static> VersionedMapMemo<C, S>
+where
+    C: TypeConstructor,
+    S: for<'a, 'store> Fn(
+        &'a C::Type<'store>,
+        ReadContext<'store>,
+    ) -> &'a VersionedMap<'store, K, V>,
+{
+    pub fn new(store: &Store<C>, selector: S) -> Self {
+        Self::with_change_detection(store, selector, ChangeDetection::Hashed)
+    }
+
+    /// Returns a new memo that detects changes to the entries of the selected map with the given
+    /// `change_detection` mode.
+    pub fn with_change_detection(
+        store: &Store<C>,
+        selector: S,
+        change_detection: ChangeDetection,
+    ) -> Self {
+        let versions = store.with(|root, cx| {
+            let map = selector(root, cx);
+
+            CollectionVersions::new(
+                change_detection,
+                map.structure_version(),
+                map.deref(cx).values(),
+            )
+        });
+
+        VersionedMapMemo {
+            selector,
+            store_id: store.id(),
+            versions,
+            _marker: marker::PhantomData,
+        }
+    }
+}
+
+impl<'a, 'b, 'store, C, S, K: Eq + Hash + 'static, V: 'static> MemoLifetime<'a, 'b, 'store>
+    for VersionedMapMemo<C, S>
+where
+    C: TypeConstructor + 'static,
+    S: Fn(&'b C::Type<'store>, ReadContext<'store>) -> &'b VersionedMap<'store, K, V> + 'static,
+{
+    type Value = CollectionChange<'b, VersionedMap<'store, K, V>>;
+}
+
+impl<C, S, K: Eq + Hash + 'static, V: 'static> Memo for VersionedMapMemo<C, S>
+where
+    C: TypeConstructor + 'static,
+    S: for<'a, 'store> Fn(
+            &'a C::Type<'store>,
+            ReadContext<'store>,
+        ) -> &'a VersionedMap<'store, K, V>
+        + 'static,
+{
+    type RootTC = C;
+
+    fn store_id(&self) -> usize {
+        self.store_id
+    }
+
+    fn refresh_unchecked<'a, 'b, 'store: 'b>(
+        &'a mut self,
+        root: &'b C::Type<'store>,
+        cx: ReadContext<'store>,
+    ) -> Refresh<<Self as MemoLifetime<'a, 'b, 'store>>::Value> {
+        let map = (self.selector)(root, cx);
+        let change = self
+            .versions
+            .update(map.structure_version(), map.deref(cx).values());
+
+        change.with_collection(map)
+    }
+}
+
+struct CollectionVersions {
+    structure: u64,
+    elements: SliceVersions,
+}
+
+impl CollectionVersions {
+    fn new<'a, 'store: 'a, T: 'static>(
+        change_detection: ChangeDetection,
+        structure: u64,
+        cells: impl IntoIterator<Item = &'a VersionedCell<'store, T>>,
+    ) -> Self {
+        CollectionVersions {
+            structure,
+            elements: SliceVersions::new(
+                change_detection,
+                cells.into_iter().map(|cell| cell.version()),
+            ),
+        }
+    }
+
+    /// Replaces the last versions with the `structure` version and the versions of the `cells`,
+    /// and returns what changed.
+    fn update<'a, 'store: 'a, T: 'static>(
+        &mut self,
+        structure: u64,
+        cells: impl IntoIterator<Item = &'a VersionedCell<'store, T>>,
+    ) -> Change {
+        let is_structure_changed = structure != self.structure;
+        let is_elements_changed = self
+            .elements
+            .update(cells.into_iter().map(|cell| cell.version()));
+
+        self.structure = structure;
+
+        Change {
+            is_structure_changed,
+            is_element_changed: !is_structure_changed && is_elements_changed,
+        }
+    }
+}
+
+struct Change {
+    is_structure_changed: bool,
+    is_element_changed: bool,
+}
+
+impl Change {
+    fn with_collection<X>(self, collection: &X) -> Refresh<CollectionChange<'_, X>> {
+        Refresh {
+            value: CollectionChange {
+                collection,
+                is_structure_changed: self.is_structure_changed,
+                is_element_changed: self.is_element_changed,
+            },
+            is_changed: self.is_structure_changed || self.is_element_changed,
+        }
+    }
+}
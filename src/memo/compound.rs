@@ -0,0 +1,154 @@
+use crate::memo::{Memo, MemoLifetime, Refresh};
+use crate::store::ReadContext;
+use crate::TypeConstructor;
+
+// Tuples of memos (for the same store) are memos themselves. The value of a tuple memo is the
+// tuple of the values of its memos; a tuple memo is changed if any of its memos changed. Every memo
+// in the tuple is refreshed on every refresh, even if an earlier memo already changed.
+//
+// A tuple memo reports the store ID of its first memo. The other memos are checked against the
+// store of the read context when they are refreshed.
+
+macro_rules! tuple_memo {
+    ($($memo:ident $index:tt),*) => {
+        impl<'a, 'b, 'store, $($memo),*> MemoLifetime<'a, 'b, 'store> for ($($memo,)*)
+        where
+            $($memo: MemoLifetime<'a, 'b, 'store>,)*
+        {
+            type Value = ($(<$memo as MemoLifetime<'a, 'b, 'store>>::Value,)*);
+        }
+
+        #[allow(non_snake_case)]
+        impl<C, $($memo),*> Memo for ($($memo,)*)
+        where
+            C: TypeConstructor,
+            $($memo: Memo<RootTC = C>,)*
+        {
+            type RootTC = C;
+
+            fn store_id(&self) -> usize {
+                self.0.store_id()
+            }
+
+            fn refresh_unchecked<'a, 'b, 'store: 'b>(
+                &'a mut self,
+                root: &'b C::Type<'store>,
+                cx: ReadContext<'store>,
+            ) -> Refresh<<Self as MemoLifetime<'a, 'b, 'store>>::Value> {
+                let ($($memo,)*) = self;
+
+                $(let $memo = $memo.refresh(root, cx);)*
+
+                Refresh {
+                    is_changed: $($memo.is_changed)||*,
+                    value: ($($memo.value,)*),
+                }
+            }
+        }
+    };
+}
+
+tuple_memo!(M0 0);
+tuple_memo!(M0 0, M1 1);
+tuple_memo!(M0 0, M1 1, M2 2);
+tuple_memo!(M0 0, M1 1, M2 2, M3 3);
+tuple_memo!(M0 0, M1 1, M2 2, M3 3, M4 4);
+tuple_memo!(M0 0, M1 1, M2 2, M3 3, M4 4, M5 5);
+tuple_memo!(M0 0, M1 1, M2 2, M3 3, M4 4, M5 5, M6 6);
+tuple_memo!(M0 0, M1 1, M2 2, M3 3, M4 4, M5 5, M6 6, M7 7);
+tuple_memo!(M0 0, M1 1, M2 2, M3 3, M4 4, M5 5, M6 6, M7 7, M8 8);
+tuple_memo!(M0 0, M1 1, M2 2, M3 3, M4 4, M5 5, M6 6, M7 7, M8 8, M9 9);
+tuple_memo!(M0 0, M1 1, M2 2, M3 3, M4 4, M5 5, M6 6, M7 7, M8 8, M9 9, M10 10);
+tuple_memo!(M0 0, M1 1, M2 2, M3 3, M4 4, M5 5, M6 6, M7 7, M8 8, M9 9, M10 10, M11 11);
+tuple_memo!(M0 0, M1 1, M2 2, M3 3, M4 4, M5 5, M6 6, M7 7, M8 8, M9 9, M10 10, M11 11, M12 12);
+tuple_memo!(M0 0, M1 1, M2 2, M3 3, M4 4, M5 5, M6 6, M7 7, M8 8, M9 9, M10 10, M11 11, M12 12, M13 13);
+tuple_memo!(M0 0, M1 1, M2 2, M3 3, M4 4, M5 5, M6 6, M7 7, M8 8, M9 9, M10 10, M11 11, M12 12, M13 13, M14 14);
+tuple_memo!(M0 0, M1 1, M2 2, M3 3, M4 4, M5 5, M6 6, M7 7, M8 8, M9 9, M10 10, M11 11, M12 12, M13 13, M14 14, M15 15);
+
+// Arrays and vectors of memos of the same type are memos as well; their value is an array or
+// vector of the values of their memos. This allows watching an arbitrary number of memos (e.g. by
+// nesting tuples, or with a vector of memos of the same type).
+//
+// Like a tuple memo, an array or vector memo reports the store ID of its first memo. An empty array
+// or vector is not associated with any store, so asking for its store ID panics.
+
+impl<'a, 'b, 'store, M, const N: usize> MemoLifetime<'a, 'b, 'store> for [M; N]
+where
+    M: MemoLifetime<'a, 'b, 'store>,
+{
+    type Value = [<M as MemoLifetime<'a, 'b, 'store>>::Value; N];
+}
+
+impl<C, M, const N: usize> Memo for [M; N]
+where
+    C: TypeConstructor,
+    M: Memo<RootTC = C>,
+{
+    type RootTC = C;
+
+    fn store_id(&self) -> usize {
+        self.first()
+            .expect("an empty collection of memos is not associated with a store")
+            .store_id()
+    }
+
+    fn refresh_unchecked<'a, 'b, 'store: 'b>(
+        &'a mut self,
+        root: &'b C::Type<'store>,
+        cx: ReadContext<'store>,
+    ) -> Refresh<<Self as MemoLifetime<'a, 'b, 'store>>::Value> {
+        let mut is_changed = false;
+
+        let value = self.each_mut().map(|memo| {
+            let refreshed = memo.refresh(root, cx);
+
+            is_changed |= refreshed.is_changed;
+
+            refreshed.value
+        });
+
+        Refresh { value, is_changed }
+    }
+}
+
+impl<'a, 'b, 'store, M> MemoLifetime<'a, 'b, 'store> for Vec<M>
+where
+    M: MemoLifetime<'a, 'b, 'store>,
+{
+    type Value = Vec<<M as MemoLifetime<'a, 'b, 'store>>::Value>;
+}
+
+impl<C, M> Memo for Vec<M>
+where
+    C: TypeConstructor,
+    M: Memo<RootTC = C>,
+{
+    type RootTC = C;
+
+    fn store_id(&self) -> usize {
+        self.first()
+            .expect("an empty collection of memos is not associated with a store")
+            .store_id()
+    }
+
+    fn refresh_unchecked<'a, 'b, 'store: 'b>(
+        &'a mut self,
+        root: &'b C::Type<'store>,
+        cx: ReadContext<'store>,
+    ) -> Refresh<<Self as MemoLifetime<'a, 'b, 'store>>::Value> {
+        let mut is_changed = false;
+
+        let value = self
+            .iter_mut()
+            .map(|memo| {
+                let refreshed = memo.refresh(root, cx);
+
+                is_changed |= refreshed.is_changed;
+
+                refreshed.value
+            })
+            .collect();
+
+        Refresh { value, is_changed }
+    }
+}
@@ -0,0 +1,110 @@
+use crate::memo::{Memo, MemoLifetime, Refresh};
+use crate::store::{ReadContext, Store};
+use crate::TypeConstructor;
+
+/// Memo that caches a value computed from the values of one or more input memos.
+///
+/// The value is only recomputed when an input memo changed; the [DerivedMemo] changes whenever it
+/// recomputes its value. Unlike an [OwnedMemo](crate::memo::OwnedMemo), this does not require the
+/// value to implement `PartialEq`. To ignore recomputations that produce an equal value, see
+/// [MemoExt::filter_changes](crate::memo::MemoExt::filter_changes).
+///
+/// To derive a value from more than one memo, pass a tuple of memos as the input:
+///
+/// ```ignore
+/// let visible_items = DerivedMemo::new(&store, (items_memo, filter_memo), |(items, filter), cx| {
+///     items
+///         .iter()
+///         .filter(|item| filter.deref(cx).matches(item.deref(cx)))
+///         .map(|item| item.deref(cx).clone())
+///         .collect::<Vec<_>>()
+/// });
+/// ```
+pub struct DerivedMemo<M, F, T> {
+    input: M,
+    compute: F,
+    value: Option<T>,
+}
+
+/// Memo that derives an owned value from the value of another memo, see [MemoExt::map].
+///
+/// A [DerivedMemo] that computes its value on the first refresh rather than when it is created.
+///
+/// [MemoExt::map]: crate::memo::MemoExt::map
+pub type Map<M, F, T> = DerivedMemo<M, F, T>;
+
+impl<C, M, F, T> DerivedMemo<M, F, T>
+where
+    C: TypeConstructor,
+    M: Memo<RootTC = C>,
+    F: for<'a, 'b, 'store> Fn(<M as MemoLifetime<'a, 'b, 'store>>::Value, ReadContext<'store>) -> T,
+{
+    /// Returns a new memo that computes its value from the values of the `input` memo with
+    /// `compute`.
+    ///
+    /// The value is computed immediately.
+    pub fn new(store: &Store<C>, mut input: M, compute: F) -> Self {
+        let value = store.with(|root, cx| compute(input.refresh(root, cx).value, cx));
+
+        DerivedMemo {
+            input,
+            compute,
+            value: Some(value),
+        }
+    }
+
+    /// Returns a new memo that computes its value on the first refresh, see [MemoExt::map].
+    ///
+    /// [MemoExt::map]: crate::memo::MemoExt::map
+    pub(crate) fn lazy(input: M, compute: F) -> Self {
+        DerivedMemo {
+            input,
+            compute,
+            value: None,
+        }
+    }
+}
+
+impl<'a, 'b, 'store, M, F, T: 'static> MemoLifetime<'a, 'b, 'store> for DerivedMemo<M, F, T>
+where
+    M: Memo,
+    F: for<'c, 'd, 'e> Fn(<M as MemoLifetime<'c, 'd, 'e>>::Value, ReadContext<'e>) -> T + 'static,
+{
+    type Value = &'a T;
+}
+
+impl<M, F, T: 'static> Memo for DerivedMemo<M, F, T>
+where
+    M: Memo,
+    F: for<'a, 'b, 'store> Fn(<M as MemoLifetime<'a, 'b, 'store>>::Value, ReadContext<'store>) -> T
+        + 'static,
+{
+    type RootTC = M::RootTC;
+
+    fn store_id(&self) -> usize {
+        self.input.store_id()
+    }
+
+    fn refresh_unchecked<'a, 'b, 'store: 'b>(
+        &'a mut self,
+        root: &'b <M::RootTC as TypeConstructor>::Type<'store>,
+        cx: ReadContext<'store>,
+    ) -> Refresh<<Self as MemoLifetime<'a, 'b, 'store>>::Value> {
+        let DerivedMemo {
+            input,
+            compute,
+            value,
+        } = self;
+
+        let refreshed = input.refresh_unchecked(root, cx);
+
+        if refreshed.is_changed || value.is_none() {
+            *value = Some(compute(refreshed.value, cx));
+        }
+
+        Refresh {
+            value: value.as_ref().unwrap(),
+            is_changed: refreshed.is_changed,
+        }
+    }
+}
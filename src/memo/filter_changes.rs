@@ -0,0 +1,73 @@
+use crate::memo::{Memo, MemoLifetime, Refresh};
+use crate::store::ReadContext;
+use crate::TypeConstructor;
+
+/// Memo that only reports changes to the value of another memo for which a predicate holds, see
+/// [MemoExt::filter_changes].
+///
+/// [MemoExt::filter_changes]: crate::memo::MemoExt::filter_changes
+pub struct FilterChanges<M, P, T> {
+    memo: M,
+    predicate: P,
+    last_value: Option<T>,
+}
+
+impl<M, P, T> FilterChanges<M, P, T> {
+    pub(crate) fn new(memo: M, predicate: P) -> Self {
+        FilterChanges {
+            memo,
+            predicate,
+            last_value: None,
+        }
+    }
+}
+
+impl<'a, 'b, 'store, M, P, T: 'static> MemoLifetime<'a, 'b, 'store> for FilterChanges<M, P, T>
+where
+    M: MemoLifetime<'a, 'b, 'store, Value = &'a T>,
+{
+    type Value = &'a T;
+}
+
+impl<M, P, T> Memo for FilterChanges<M, P, T>
+where
+    M: Memo + for<'a, 'b, 'store> MemoLifetime<'a, 'b, 'store, Value = &'a T>,
+    P: Fn(&T, &T) -> bool,
+    T: Clone + 'static,
+{
+    type RootTC = M::RootTC;
+
+    fn store_id(&self) -> usize {
+        self.memo.store_id()
+    }
+
+    fn refresh_unchecked<'a, 'b, 'store: 'b>(
+        &'a mut self,
+        root: &'b <M::RootTC as TypeConstructor>::Type<'store>,
+        cx: ReadContext<'store>,
+    ) -> Refresh<<Self as MemoLifetime<'a, 'b, 'store>>::Value> {
+        let FilterChanges {
+            memo,
+            predicate,
+            last_value,
+        } = self;
+
+        let refreshed = memo.refresh_unchecked(root, cx);
+
+        // Compare against the last value for which a change was reported (or the value on the
+        // first refresh), so that changes that are each filtered out still add up.
+        let is_changed = match last_value {
+            Some(last_value) => refreshed.is_changed && predicate(last_value, refreshed.value),
+            None => refreshed.is_changed,
+        };
+
+        if is_changed || last_value.is_none() {
+            *last_value = Some(refreshed.value.clone());
+        }
+
+        Refresh {
+            value: refreshed.value,
+            is_changed,
+        }
+    }
+}
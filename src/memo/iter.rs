@@ -1,58 +1,67 @@
-use std::borrow::Borrow;
-use std::hash::{Hash, Hasher};
 use std::marker;
 
-use seahash::SeaHasher;
-
+use crate::memo::{ChangeDetection, Memo, MemoLifetime, Refresh, SliceVersions};
 use crate::store::{ReadContext, Store};
 use crate::versioned_cell::VersionedCell;
 use crate::TypeConstructor;
 
-// TODO: this doesn't work... Below is my best attempt. It will actually compile here, but when
-// one tries to create a new `CellIterMemo`, it fails with an error like this
-//
-//     error: implementation of `IntoIterSelector` is not general enough
-//       --> examples/playground.rs:51:25
-//        |
-//     51 |     let mut iter_memo = CellIterMemo::new(&store, |root: &MyRoot, cx| root.elements.iter());
-//        |                         ^^^^^^^^^^^^^^^^^ implementation of `IntoIterSelector` is not general enough
-//        |
-//        = note: `[closure@examples/playground.rs:51:51: 51:91]` must implement `IntoIterSelector<'0, 'store, MyRootTC, Element>`, for any lifetime `'0`...
-//        = note: ...but it actually implements `IntoIterSelector<'_, '_, MyRootTC, Element>`
-//
-// Seemingly, the compiler uses the blanket implementation below to generate one specific
-// implementation. A later stage (borrow check?) then finds that the bound must hold for any
-// `<'a, 'store>`, but only finds the one specific implementation. The compiler seems (currently)
-// not quite smart enough to realise that the blanket implementation could be used to generate an
-// implementation for every `<'a, 'store>`.
-//
-// I'm not sure if there is a different way to express this that does work on current nightly, or
-// if the compiler can/will ever be upgraded to accept this code. Note that there is also the
-// definite possibility that the compiler is correctly rejecting this code and that it is only my
-// understanding that is lacking.
-
-pub trait IntoIterSelector<'a, 'store, C: TypeConstructor, T: 'static> {
-    type IntoIter: IntoIterator<Item = Self::Item>;
-
-    type Item: Borrow<VersionedCell<'store, T>> + 'a;
-
-    fn select(&self, root: &'a C::Type<'store>, cx: ReadContext<'store>) -> Self::IntoIter;
+/// A boxed iterator over [VersionedCell]s, see [CellIterSelector].
+pub type BoxedCellIter<'a, 'store, T> = Box<dyn Iterator<Item = &'a VersionedCell<'store, T>> + 'a>;
+
+/// Selects an iterator over [VersionedCell]s for a [CellIterMemo].
+///
+/// Closures cannot (currently) be inferred to return an iterator type that depends on the lifetimes
+/// of their arguments, so instead of a plain closure, a [CellIterMemo] takes a selector that
+/// implements this trait. The trait is implemented for any closure that returns a
+/// [BoxedCellIter]; use [CellIterMemo::from_fn] to create a memo from such a closure:
+///
+/// ```ignore
+/// let memo = CellIterMemo::from_fn(&store, |root, _| {
+///     Box::new(root.elements.values().filter(|cell| ...))
+/// });
+/// ```
+///
+/// To avoid boxing, implement this trait for a custom selector type instead:
+///
+/// ```ignore
+/// struct ElementsSelector;
+///
+/// impl CellIterSelector<MyRootTC, Element> for ElementsSelector {
+///     type Iter<'a, 'store: 'a> = hash_map::Values<'a, u32, VersionedCell<'store, Element>>;
+///
+///     fn select<'a, 'store: 'a>(
+///         &self,
+///         root: &'a MyRoot<'store>,
+///         cx: ReadContext<'store>,
+///     ) -> Self::Iter<'a, 'store> {
+///         root.elements.values()
+///     }
+/// }
+/// ```
+pub trait CellIterSelector<C: TypeConstructor, T: 'static> {
+    /// The type of iterator that is selected.
+    type Iter<'a, 'store: 'a>: Iterator<Item = &'a VersionedCell<'store, T>>;
+
+    /// Selects the iterator from the given `root`.
+    fn select<'a, 'store: 'a>(
+        &self,
+        root: &'a C::Type<'store>,
+        cx: ReadContext<'store>,
+    ) -> Self::Iter<'a, 'store>;
 }
 
-impl<'a, 'store: 'a, C: TypeConstructor, T: 'static, F, I> IntoIterSelector<'a, 'store, C, T> for F
+impl<C, T: 'static, F> CellIterSelector<C, T> for F
 where
-    F: Fn(&'a C::Type<'store>, ReadContext<'store>) -> I,
-    I: IntoIterator,
-    I::Item: Borrow<VersionedCell<'store, T>> + 'a,
+    C: TypeConstructor,
+    F: for<'a, 'store> Fn(&'a C::Type<'store>, ReadContext<'store>) -> BoxedCellIter<'a, 'store, T>,
 {
-    type IntoIter = I;
-    type Item = I::Item;
+    type Iter<'a, 'store: 'a> = BoxedCellIter<'a, 'store, T>;
 
-    fn select(
+    fn select<'a, 'store: 'a>(
         &self,
-        root: &'a <C as TypeConstructor>::Type<'store>,
+        root: &'a C::Type<'store>,
         cx: ReadContext<'store>,
-    ) -> Self::IntoIter {
+    ) -> Self::Iter<'a, 'store> {
         self(root, cx)
     }
 }
@@ -60,31 +69,86 @@ where
 pub struct CellIterMemo<C, S, T> {
     selector: S,
     store_id: usize,
-    last_version: u64,
+    last_versions: SliceVersions,
     _marker: marker::PhantomData<(*const C, *const T)>,
 }
 
 impl<C, S, T: 'static> CellIterMemo<C, S, T>
 where
     C: TypeConstructor,
-    S: for<'a, 'store> IntoIterSelector<'a, 'store, C, T>,
+    S: CellIterSelector<C, T>,
 {
     pub fn new(store: &Store<C>, selector: S) -> Self {
-        let last_version = store.with(|root, cx| {
-            let mut hasher = SeaHasher::new();
-
-            for cell in selector.select(root, cx) {
-                cell.borrow().version().hash(&mut hasher);
-            }
+        Self::with_change_detection(store, selector, ChangeDetection::Hashed)
+    }
 
-            hasher.finish()
+    /// Returns a new memo that detects changes to the selected cells with the given
+    /// `change_detection` mode.
+    pub fn with_change_detection(
+        store: &Store<C>,
+        selector: S,
+        change_detection: ChangeDetection,
+    ) -> Self {
+        let last_versions = store.with(|root, cx| {
+            SliceVersions::new(
+                change_detection,
+                selector.select(root, cx).map(|cell| cell.version()),
+            )
         });
 
         CellIterMemo {
             selector,
             store_id: store.id(),
-            last_version,
+            last_versions,
             _marker: marker::PhantomData,
         }
     }
 }
+
+impl<C, F, T: 'static> CellIterMemo<C, F, T>
+where
+    C: TypeConstructor,
+    F: for<'a, 'store> Fn(&'a C::Type<'store>, ReadContext<'store>) -> BoxedCellIter<'a, 'store, T>,
+{
+    /// Returns a new memo for the iterator selected by the closure `f`.
+    ///
+    /// Equivalent to [CellIterMemo::new], but helps the compiler infer the closure's signature.
+    pub fn from_fn(store: &Store<C>, f: F) -> Self {
+        CellIterMemo::new(store, f)
+    }
+}
+
+impl<'a, 'b, 'store, C, S, T: 'static> MemoLifetime<'a, 'b, 'store> for CellIterMemo<C, S, T>
+where
+    C: TypeConstructor + 'static,
+    S: CellIterSelector<C, T> + 'static,
+{
+    type Value = S::Iter<'b, 'store>;
+}
+
+impl<C, S, T: 'static> Memo for CellIterMemo<C, S, T>
+where
+    C: TypeConstructor + 'static,
+    S: CellIterSelector<C, T> + 'static,
+{
+    type RootTC = C;
+
+    fn store_id(&self) -> usize {
+        self.store_id
+    }
+
+    fn refresh_unchecked<'a, 'b, 'store: 'b>(
+        &'a mut self,
+        root: &'b C::Type<'store>,
+        cx: ReadContext<'store>,
+    ) -> Refresh<<Self as MemoLifetime<'a, 'b, 'store>>::Value> {
+        let is_changed = self
+            .last_versions
+            .update(self.selector.select(root, cx).map(|cell| cell.version()));
+
+        Refresh {
+            value: self.selector.select(root, cx),
+            is_changed,
+        }
+    }
+}
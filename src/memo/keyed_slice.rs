@@ -0,0 +1,439 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::marker;
+
+use crate::memo::{Memo, MemoLifetime, Refresh};
+use crate::store::{ReadContext, Store};
+use crate::versioned_cell::VersionedCell;
+use crate::TypeConstructor;
+
+/// The changes to a keyed slice between two refreshes of a [KeyedSliceMemo].
+///
+/// Indices in `inserted` and `updated` refer to positions in the current slice; keys in `removed`
+/// refer to items that were present in the previous slice. Applying the removals, then the moves,
+/// then the insertions to the previous list produces the order of the current slice.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KeyedSliceDiff<K> {
+    /// The indices of items whose key was not present in the previous slice, in ascending order.
+    pub inserted: Vec<usize>,
+    /// The keys of items that are no longer present in the slice.
+    pub removed: Vec<K>,
+    /// Items that are present in both slices, but whose position relative to the other retained
+    /// items changed.
+    ///
+    /// This is a minimal set: the retained items that are not reported as moved are still in the
+    /// same relative order.
+    pub moved: Vec<Move>,
+    /// The indices of items that were present in the previous slice, but whose cell version
+    /// changed, in ascending order.
+    pub updated: Vec<usize>,
+}
+
+impl<K> KeyedSliceDiff<K> {
+    fn new() -> Self {
+        KeyedSliceDiff {
+            inserted: Vec::new(),
+            removed: Vec::new(),
+            moved: Vec::new(),
+            updated: Vec::new(),
+        }
+    }
+
+    /// Whether nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.inserted.is_empty()
+            && self.removed.is_empty()
+            && self.moved.is_empty()
+            && self.updated.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.inserted.clear();
+        self.removed.clear();
+        self.moved.clear();
+        self.updated.clear();
+    }
+}
+
+/// An item that moved, see [KeyedSliceDiff::moved].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Move {
+    /// The item's index in the previous slice.
+    pub from: usize,
+    /// The item's index in the current slice.
+    pub to: usize,
+}
+
+/// The value of a [KeyedSliceMemo].
+pub struct KeyedSliceChange<'a, 'b, 'store, T, K> {
+    /// The selected slice.
+    pub slice: &'b [VersionedCell<'store, T>],
+    /// The changes since the previous refresh.
+    pub diff: &'a KeyedSliceDiff<K>,
+}
+
+/// Memo for a slice of cells that identifies items by a key and reports a [KeyedSliceDiff] of
+/// the changes on every refresh.
+///
+/// Where a [CellSliceMemo](crate::memo::CellSliceMemo) only reports that something in the slice
+/// changed, this memo reports which items were inserted, removed, moved or updated, so that a
+/// view of the list can be patched incrementally rather than rebuilt.
+///
+/// Keys are expected to be unique within the slice; if they are not, the diff is unspecified.
+///
+/// ```ignore
+/// let mut rows = KeyedSliceMemo::new(&store, |root, cx| root.rows.deref(cx), |row| row.id);
+///
+/// store.with(|root, cx| {
+///     let Refresh { value, .. } = rows.refresh(root, cx);
+///
+///     for key in &value.diff.removed {
+///         view.remove_row(key);
+///     }
+///
+///     for index in &value.diff.inserted {
+///         view.insert_row(*index, value.slice[*index].deref(cx));
+///     }
+/// });
+/// ```
+pub struct KeyedSliceMemo<C, S, F, K> {
+    selector: S,
+    key: F,
+    store_id: usize,
+    entries: Vec<Entry<K>>,
+    diff: KeyedSliceDiff<K>,
+    _marker: marker::PhantomData<*const C>,
+}
+
+struct Entry<K> {
+    key: K,
+    version: u64,
+}
+
+impl<C, S, F, T: 'static, K> KeyedSliceMemo<C, S, F, K>
+where
+    C: TypeConstructor,
+    S: for<'a, 'store> Fn(
+        &'a C::Type<'store>,
+        ReadContext<'store>,
+    ) -> &'a [VersionedCell<'store, T>],
+    F: Fn(&T) -> K,
+    K: Eq + Hash,
+{
+    /// Returns a new memo for the slice selected by `selector` that identifies items by the key
+    /// returned by `key`.
+    pub fn new(store: &Store<C>, selector: S, key: F) -> Self {
+        let entries = store.with(|root, cx| entries(selector(root, cx), &key, cx));
+
+        KeyedSliceMemo {
+            selector,
+            key,
+            store_id: store.id(),
+            entries,
+            diff: KeyedSliceDiff::new(),
+            _marker: marker::PhantomData,
+        }
+    }
+}
+
+impl<'a, 'b, 'store, C, S, F, T: 'static, K: 'static> MemoLifetime<'a, 'b, 'store>
+    for KeyedSliceMemo<C, S, F, K>
+where
+    C: TypeConstructor + 'static,
+    S: Fn(&'b C::Type<'store>, ReadContext<'store>) -> &'b [VersionedCell<'store, T>] + 'static,
+    F: Fn(&T) -> K + 'static,
+{
+    type Value = KeyedSliceChange<'a, 'b, 'store, T, K>;
+}
+
+impl<C, S, F, T: 'static, K: 'static> Memo for KeyedSliceMemo<C, S, F, K>
+where
+    C: TypeConstructor + 'static,
+    S: for<'a, 'store> Fn(
+            &'a C::Type<'store>,
+            ReadContext<'store>,
+        ) -> &'a [VersionedCell<'store, T>]
+        + 'static,
+    F: Fn(&T) -> K + 'static,
+    K: Eq + Hash + Clone,
+{
+    type RootTC = C;
+
+    fn store_id(&self) -> usize {
+        self.store_id
+    }
+
+    fn refresh_unchecked<'a, 'b, 'store: 'b>(
+        &'a mut self,
+        root: &'b C::Type<'store>,
+        cx: ReadContext<'store>,
+    ) -> Refresh<<Self as MemoLifetime<'a, 'b, 'store>>::Value> {
+        let slice = (self.selector)(root, cx);
+
+        self.diff.clear();
+
+        // A cell's value cannot change without its version changing, so if all versions are
+        // unchanged, all keys are unchanged as well and we can skip recomputing them.
+        let is_unchanged = slice.len() == self.entries.len()
+            && slice
+                .iter()
+                .zip(&self.entries)
+                .all(|(cell, entry)| cell.version() == entry.version);
+
+        if !is_unchanged {
+            let entries = entries(slice, &self.key, cx);
+
+            diff(&self.entries, &entries, &mut self.diff);
+
+            self.entries = entries;
+        }
+
+        Refresh {
+            value: KeyedSliceChange {
+                slice,
+                diff: &self.diff,
+            },
+            is_changed: !self.diff.is_empty(),
+        }
+    }
+}
+
+fn entries<'store, T, K>(
+    slice: &[VersionedCell<'store, T>],
+    key: impl Fn(&T) -> K,
+    cx: ReadContext<'store>,
+) -> Vec<Entry<K>> {
+    slice
+        .iter()
+        .map(|cell| Entry {
+            key: key(cell.deref(cx)),
+            version: cell.version(),
+        })
+        .collect()
+}
+
+fn diff<K: Eq + Hash + Clone>(
+    previous: &[Entry<K>],
+    current: &[Entry<K>],
+    diff: &mut KeyedSliceDiff<K>,
+) {
+    let previous_indices: HashMap<&K, usize> = previous
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| (&entry.key, index))
+        .collect();
+
+    // Pairs of (previous index, current index) for the items that are present in both slices, in
+    // current order.
+    let mut retained = Vec::new();
+
+    for (index, entry) in current.iter().enumerate() {
+        if let Some(&previous_index) = previous_indices.get(&entry.key) {
+            if previous[previous_index].version != entry.version {
+                diff.updated.push(index);
+            }
+
+            retained.push((previous_index, index));
+        } else {
+            diff.inserted.push(index);
+        }
+    }
+
+    if retained.len() < previous.len() {
+        let current_keys: HashSet<&K> = current.iter().map(|entry| &entry.key).collect();
+
+        diff.removed.extend(
+            previous
+                .iter()
+                .filter(|entry| !current_keys.contains(&entry.key))
+                .map(|entry| entry.key.clone()),
+        );
+    }
+
+    // The retained items that form the longest increasing subsequence of previous indices stay in
+    // place; all other retained items moved.
+    let is_stationary = longest_increasing_subsequence(&retained);
+
+    diff.moved.extend(
+        retained
+            .iter()
+            .zip(is_stationary)
+            .filter(|(_, is_stationary)| !is_stationary)
+            .map(|(&(from, to), _)| Move { from, to }),
+    );
+}
+
+/// Returns for each pair whether it is part of a longest subsequence of `pairs` that is strictly
+/// increasing in the first element of the pair.
+fn longest_increasing_subsequence(pairs: &[(usize, usize)]) -> Vec<bool> {
+    // `tails[l]` is the position in `pairs` of the smallest tail of an increasing subsequence of
+    // length `l + 1` found so far.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut predecessors = vec![usize::MAX; pairs.len()];
+
+    for (position, &(value, _)) in pairs.iter().enumerate() {
+        let length = tails.partition_point(|&tail| pairs[tail].0 < value);
+
+        if length > 0 {
+            predecessors[position] = tails[length - 1];
+        }
+
+        if length == tails.len() {
+            tails.push(position);
+        } else {
+            tails[length] = position;
+        }
+    }
+
+    let mut in_subsequence = vec![false; pairs.len()];
+    let mut position = tails.last().copied().unwrap_or(usize::MAX);
+
+    while position != usize::MAX {
+        in_subsequence[position] = true;
+        position = predecessors[position];
+    }
+
+    in_subsequence
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, Entry, KeyedSliceDiff, KeyedSliceMemo, Move};
+    use crate::collections::VersionedVec;
+    use crate::memo::Memo;
+    use crate::store::Store;
+
+    struct Root<'store> {
+        rows: VersionedVec<'store, (u32, &'static str)>,
+    }
+
+    crate::gen_type_constructor!(Root, RootTC);
+
+    fn entries(items: &[(u32, u64)]) -> Vec<Entry<u32>> {
+        items
+            .iter()
+            .map(|&(key, version)| Entry { key, version })
+            .collect()
+    }
+
+    fn keyed_diff(previous: &[(u32, u64)], current: &[(u32, u64)]) -> KeyedSliceDiff<u32> {
+        let mut result = KeyedSliceDiff::new();
+
+        diff(&entries(previous), &entries(current), &mut result);
+
+        result
+    }
+
+    #[test]
+    fn diff_reorder() {
+        let result = keyed_diff(
+            &[(1, 0), (2, 0), (3, 0), (4, 0)],
+            &[(1, 0), (3, 0), (4, 0), (2, 0)],
+        );
+
+        assert_eq!(result.moved, [Move { from: 1, to: 3 }]);
+        assert!(result.inserted.is_empty());
+        assert!(result.removed.is_empty());
+        assert!(result.updated.is_empty());
+    }
+
+    #[test]
+    fn diff_reverse() {
+        let result = keyed_diff(&[(1, 0), (2, 0), (3, 0)], &[(3, 0), (2, 0), (1, 0)]);
+
+        // Only one of the items can stay in place.
+        assert_eq!(result.moved.len(), 2);
+        assert!(result.inserted.is_empty());
+        assert!(result.removed.is_empty());
+    }
+
+    #[test]
+    fn diff_remove_and_insert() {
+        let result = keyed_diff(&[(1, 0), (2, 0), (3, 0)], &[(1, 0), (4, 0), (3, 0), (5, 0)]);
+
+        assert_eq!(result.inserted, [1, 3]);
+        assert_eq!(result.removed, [2]);
+        assert!(result.moved.is_empty());
+        assert!(result.updated.is_empty());
+    }
+
+    #[test]
+    fn diff_update_and_move() {
+        let result = keyed_diff(&[(1, 0), (2, 0)], &[(2, 1), (1, 0)]);
+
+        assert_eq!(result.updated, [0]);
+        assert_eq!(result.moved, [Move { from: 1, to: 0 }]);
+    }
+
+    #[test]
+    fn diff_empty_previous() {
+        let result = keyed_diff(&[], &[(1, 0), (2, 0)]);
+
+        assert_eq!(result.inserted, [0, 1]);
+        assert!(result.removed.is_empty());
+        assert!(result.moved.is_empty());
+        assert!(result.updated.is_empty());
+    }
+
+    #[test]
+    fn diff_empty_current() {
+        let result = keyed_diff(&[(1, 0), (2, 0)], &[]);
+
+        assert_eq!(result.removed, [1, 2]);
+        assert!(result.inserted.is_empty());
+        assert!(result.moved.is_empty());
+    }
+
+    #[test]
+    fn diff_unchanged() {
+        let items = [(1, 0), (2, 3), (3, 1)];
+
+        assert!(keyed_diff(&items, &items).is_empty());
+    }
+
+    #[test]
+    fn diff_duplicate_keys_does_not_panic() {
+        // The diff is unspecified, but must not panic.
+        keyed_diff(&[(1, 0), (1, 0), (2, 0)], &[(2, 0), (1, 0), (1, 1), (1, 0)]);
+        keyed_diff(&[(1, 0), (2, 0), (1, 0)], &[(1, 0)]);
+        keyed_diff(&[(1, 0)], &[(1, 0), (1, 0), (1, 0)]);
+    }
+
+    #[test]
+    fn memo_reports_diff_once() {
+        let store = Store::<RootTC>::initialize(|cx| Root {
+            rows: VersionedVec::from_vec(cx, vec![(1, "a"), (2, "b")]),
+        });
+
+        let mut memo = KeyedSliceMemo::new(
+            &store,
+            |root: &Root, cx| root.rows.deref(cx),
+            |row: &(u32, &str)| row.0,
+        );
+
+        store.with(|root, cx| assert!(!memo.refresh(root, cx).is_changed));
+
+        store.update(|root, cx| {
+            root.rows.remove(cx, 0);
+            root.rows.push(cx, (3, "c"));
+            root.rows.borrow(cx)[0].borrow_mut(cx).1 = "d";
+        });
+
+        store.with(|root, cx| {
+            let refresh = memo.refresh(root, cx);
+
+            assert!(refresh.is_changed);
+            assert_eq!(refresh.value.diff.inserted, [1]);
+            assert_eq!(refresh.value.diff.removed, [1]);
+            assert_eq!(refresh.value.diff.updated, [0]);
+            assert!(refresh.value.diff.moved.is_empty());
+        });
+
+        store.with(|root, cx| {
+            let refresh = memo.refresh(root, cx);
+
+            assert!(!refresh.is_changed);
+            assert!(refresh.value.diff.is_empty());
+        });
+    }
+}
@@ -1,3 +1,4 @@
+use crate::memo::{DerivedMemo, FilterChanges, Map};
 use crate::store::ReadContext;
 use crate::TypeConstructor;
 
@@ -42,3 +43,47 @@ pub trait Memo: for<'a, 'b, 'store> MemoLifetime<'a, 'b, 'store> {
         self.refresh_unchecked(root, cx)
     }
 }
+
+/// Combinators for memos, implemented for all memos.
+pub trait MemoExt: Memo + Sized {
+    /// Returns a memo that derives an owned value from this memo's value with `f`.
+    ///
+    /// The value is computed on the first refresh, after that it is only recomputed when this
+    /// memo changed. The returned memo changes whenever this memo changes; to ignore changes that
+    /// don't affect the derived value, see [filter_changes](MemoExt::filter_changes).
+    fn map<F, T>(self, f: F) -> Map<Self, F, T>
+    where
+        F: for<'a, 'b, 'store> Fn(
+            <Self as MemoLifetime<'a, 'b, 'store>>::Value,
+            ReadContext<'store>,
+        ) -> T,
+    {
+        DerivedMemo::lazy(self, f)
+    }
+
+    /// Returns a memo that pairs this memo with the `other` memo.
+    ///
+    /// The returned memo's value is a tuple of both values; it changes when either memo changes.
+    fn zip<M>(self, other: M) -> (Self, M)
+    where
+        M: Memo<RootTC = Self::RootTC>,
+    {
+        (self, other)
+    }
+
+    /// Returns a memo that only reports a change when `predicate` holds for the previous value
+    /// (the last value for which a change was reported) and the new value.
+    ///
+    /// Requires a memo with an owned value (e.g. an [OwnedMemo](crate::memo::OwnedMemo) or a
+    /// [map](MemoExt::map)ped memo).
+    fn filter_changes<P, T>(self, predicate: P) -> FilterChanges<Self, P, T>
+    where
+        Self: for<'a, 'b, 'store> MemoLifetime<'a, 'b, 'store, Value = &'a T>,
+        P: Fn(&T, &T) -> bool,
+        T: Clone + 'static,
+    {
+        FilterChanges::new(self, predicate)
+    }
+}
+
+impl<M: Memo> MemoExt for M {}
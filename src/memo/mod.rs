@@ -4,9 +4,26 @@ pub use self::cell::*;
 mod cell_slice;
 pub use self::cell_slice::*;
 
+mod change_detection;
+pub use self::change_detection::*;
+
+mod collections;
+pub use self::collections::*;
+
+mod compound;
+
+mod derived;
+pub use self::derived::*;
+
+mod filter_changes;
+pub use self::filter_changes::*;
+
 mod iter;
 pub use self::iter::*;
 
+mod keyed_slice;
+pub use self::keyed_slice::*;
+
 mod memo;
 pub use self::memo::*;
 
@@ -30,3 +47,6 @@ pub use self::option_node_slice::*;
 
 mod owned;
 pub use self::owned::*;
+
+mod tracked;
+pub use self::tracked::*;
@@ -1,9 +1,6 @@
-use std::hash::{Hash, Hasher};
 use std::marker;
 
-use seahash::SeaHasher;
-
-use crate::memo::{Memo, MemoLifetime, Refresh};
+use crate::memo::{ChangeDetection, Memo, MemoLifetime, OptionSliceVersions, Refresh};
 use crate::store::{ReadContext, Store};
 use crate::versioned_cell::VersionedCell;
 use crate::TypeConstructor;
@@ -11,7 +8,7 @@ use crate::TypeConstructor;
 pub struct OptionNodeSliceMemo<N, C, S> {
     selector: S,
     store_id: usize,
-    last_version: Option<u64>,
+    last_versions: OptionSliceVersions,
     _marker: marker::PhantomData<(*const C, *const N)>,
 }
 
@@ -25,22 +22,27 @@ where
     ) -> Option<&'a [VersionedCell<'store, N::Type<'store>>]>,
 {
     pub fn new(store: &Store<C>, selector: S) -> Self {
-        let last_version = store.with(|root, cx| {
-            selector(root, cx).map(|slice| {
-                let mut hasher = SeaHasher::new();
-
-                for node in slice {
-                    node.version().hash(&mut hasher);
-                }
+        Self::with_change_detection(store, selector, ChangeDetection::Hashed)
+    }
 
-                hasher.finish()
-            })
+    /// Returns a new memo that detects changes to the selected slice with the given
+    /// `change_detection` mode.
+    pub fn with_change_detection(
+        store: &Store<C>,
+        selector: S,
+        change_detection: ChangeDetection,
+    ) -> Self {
+        let last_versions = store.with(|root, cx| {
+            OptionSliceVersions::new(
+                change_detection,
+                selector(root, cx).map(|slice| slice.iter().map(|node| node.version())),
+            )
         });
 
         OptionNodeSliceMemo {
             selector,
             store_id: store.id(),
-            last_version,
+            last_versions,
             _marker: marker::PhantomData,
         }
     }
@@ -81,24 +83,13 @@ where
         cx: ReadContext<'store>,
     ) -> Refresh<<Self as MemoLifetime<'a, 'b, 'store>>::Value> {
         let option_slice = (self.selector)(root, cx);
-
-        let version = option_slice.map(|slice| {
-            let mut hasher = SeaHasher::new();
-
-            for node in slice {
-                node.version().hash(&mut hasher);
-            }
-
-            hasher.finish()
-        });
-
-        let last_version = self.last_version;
-
-        self.last_version = version;
+        let is_changed = self
+            .last_versions
+            .update(option_slice.map(|slice| slice.iter().map(|node| node.version())));
 
         Refresh {
             value: option_slice,
-            is_changed: version != last_version,
+            is_changed,
         }
     }
 }
@@ -0,0 +1,117 @@
+use std::marker;
+
+use crate::memo::{Memo, MemoLifetime, Refresh};
+use crate::store::{ReadContext, Store};
+use crate::tracker::{TrackedRead, Tracker};
+use crate::TypeConstructor;
+
+/// Memo for the value returned by a selector that automatically tracks which cells it depends on.
+///
+/// The selector is called with a tracking [ReadContext], which records every [VersionedCell] that
+/// is dereferenced with it (see [VersionedCell::deref]). The memo changes when the selector reads a
+/// different set of cells or when any of the cells it reads changed version; the value itself is
+/// never compared, so it does not need to implement `PartialEq`.
+///
+/// The selector runs on every refresh. To avoid recomputing an expensive value when nothing
+/// changed, keep the selector cheap and compute the value in a
+/// [DerivedMemo](crate::memo::DerivedMemo) that takes the [TrackedMemo] as its input.
+///
+/// ```ignore
+/// let memo = TrackedMemo::new(&store, |root, cx| {
+///     let node = root.node_element.deref(cx);
+///
+///     node.element.deref(cx).a + node.b
+/// });
+/// ```
+///
+/// [VersionedCell]: crate::versioned_cell::VersionedCell
+/// [VersionedCell::deref]: crate::versioned_cell::VersionedCell::deref
+pub struct TrackedMemo<C, S, T> {
+    selector: S,
+    store_id: usize,
+    reads: Vec<TrackedRead>,
+    value: T,
+    _marker: marker::PhantomData<*const C>,
+}
+
+impl<C, S, T: 'static> TrackedMemo<C, S, T>
+where
+    C: TypeConstructor,
+    S: for<'store> Fn(&C::Type<'store>, ReadContext<'store>) -> T,
+{
+    pub fn new(store: &Store<C>, selector: S) -> Self {
+        let (value, reads) = store.with(|root, cx| track(&selector, root, cx));
+
+        TrackedMemo {
+            selector,
+            store_id: store.id(),
+            reads,
+            value,
+            _marker: marker::PhantomData,
+        }
+    }
+}
+
+impl<'a, 'b, 'store, C, S, T: 'static> MemoLifetime<'a, 'b, 'store> for TrackedMemo<C, S, T>
+where
+    C: TypeConstructor + 'static,
+    S: Fn(&'b C::Type<'store>, ReadContext<'store>) -> T + 'static,
+{
+    type Value = &'a T;
+}
+
+impl<C, S, T: 'static> Memo for TrackedMemo<C, S, T>
+where
+    C: TypeConstructor + 'static,
+    S: for<'store> Fn(&C::Type<'store>, ReadContext<'store>) -> T + 'static,
+{
+    type RootTC = C;
+
+    fn store_id(&self) -> usize {
+        self.store_id
+    }
+
+    fn refresh_unchecked<'a, 'b, 'store: 'b>(
+        &'a mut self,
+        root: &'b C::Type<'store>,
+        cx: ReadContext<'store>,
+    ) -> Refresh<<Self as MemoLifetime<'a, 'b, 'store>>::Value> {
+        let (value, reads) = track(&self.selector, root, cx);
+
+        let is_changed = reads != self.reads;
+
+        self.value = value;
+        self.reads = reads;
+
+        Refresh {
+            value: &self.value,
+            is_changed,
+        }
+    }
+}
+
+fn track<'store, C, S, T>(
+    selector: &S,
+    root: &C::Type<'store>,
+    cx: ReadContext<'store>,
+) -> (T, Vec<TrackedRead>)
+where
+    C: TypeConstructor,
+    S: for<'s> Fn(&C::Type<'s>, ReadContext<'s>) -> T,
+{
+    let tracker = Tracker::new();
+
+    // SAFETY: the tracking context is only passed to the selector, which cannot retain it (the
+    // store lifetime is higher-ranked and does not appear in the output type), so it cannot be used
+    // after the tracker is dropped.
+    let value = selector(root, unsafe { cx.tracking(&tracker) });
+    let reads = tracker.into_reads();
+
+    // If this memo is itself refreshed inside another tracked memo's selector, then the other
+    // memo depends on the same cells.
+    if let Some(outer) = cx.tracker() {
+        outer.extend(&reads);
+    }
+
+    (value, reads)
+}
@@ -0,0 +1,311 @@
+//! Composable selectors for the cells in a store's data graph.
+//!
+//! Selectors are usually generated with `#[derive(Selectors)]` (requires the `derive` feature),
+//! starting from [NodeSelector::root]:
+//!
+//! ```ignore
+//! #[derive(TypeConstructor, Selectors)]
+//! struct MyRoot<'store> {
+//!     element: VersionedCell<'store, Element>,
+//!     node_element: VersionedCell<'store, NodeElement<'store>>,
+//!     elements: Vec<VersionedCell<'store, Element>>,
+//! }
+//!
+//! #[derive(TypeConstructor, Selectors)]
+//! struct NodeElement<'store> {
+//!     element: VersionedCell<'store, Element>,
+//! }
+//!
+//! let root = NodeSelector::<MyRoot<'static>>::root();
+//!
+//! let element_memo = root.element().memo(&store);
+//! let elements_memo = root.elements().memo(&store);
+//! let nested_memo = root.node_element().node().element().memo(&store);
+//! ```
+
+use std::sync::Arc;
+
+use crate::memo::{
+    CellMemo, CellSliceMemo, NodeMemo, NodeSliceMemo, OptionCellMemo, OptionNodeMemo,
+};
+use crate::store::{ReadContext, Store};
+use crate::versioned_cell::VersionedCell;
+use crate::TypeConstructor;
+
+/// Selects a node in the data graph of a store with root type constructor `R`.
+///
+/// Unlike the other selectors, a [NodeSelector] does not select a cell, but a node value (the root,
+/// or the value inside of a node cell). It cannot be turned into a memo; instead it serves as the
+/// starting point for selecting the node's cells.
+pub struct NodeSelector<R, N = R>
+where
+    R: TypeConstructor,
+    N: TypeConstructor,
+{
+    select: Arc<SelectNode<R, N>>,
+}
+
+type SelectNode<R, N> = dyn for<'a, 'store> Fn(
+        &'a <R as TypeConstructor>::Type<'store>,
+        ReadContext<'store>,
+    ) -> &'a <N as TypeConstructor>::Type<'store>
+    + Send
+    + Sync;
+
+impl<R> NodeSelector<R>
+where
+    R: TypeConstructor + 'static,
+{
+    /// Returns a selector that selects the root of the store.
+    pub fn root() -> Self {
+        NodeSelector::from_fn(|root, _| root)
+    }
+}
+
+impl<R, N> NodeSelector<R, N>
+where
+    R: TypeConstructor + 'static,
+    N: TypeConstructor + 'static,
+{
+    fn from_fn<F>(f: F) -> Self
+    where
+        F: for<'a, 'store> Fn(&'a R::Type<'store>, ReadContext<'store>) -> &'a N::Type<'store>
+            + Send
+            + Sync
+            + 'static,
+    {
+        NodeSelector {
+            select: Arc::new(f),
+        }
+    }
+
+    /// Selects the node from the given `root`.
+    pub fn select<'a, 'store>(
+        &self,
+        root: &'a R::Type<'store>,
+        cx: ReadContext<'store>,
+    ) -> &'a N::Type<'store> {
+        (self.select)(root, cx)
+    }
+}
+
+impl<R, N> Clone for NodeSelector<R, N>
+where
+    R: TypeConstructor,
+    N: TypeConstructor,
+{
+    fn clone(&self) -> Self {
+        NodeSelector {
+            select: self.select.clone(),
+        }
+    }
+}
+
+macro_rules! selector {
+    (
+        $(#[$attr:meta])*
+        $selector:ident<$param:ident: $bound:tt>,
+        $(#[$boxed_attr:meta])*
+        $boxed:ident,
+        $(#[$map_attr:meta])*
+        $map:ident,
+        $memo:ty,
+        $output:ty
+    ) => {
+        $(#[$attr])*
+        pub struct $selector<R, $param>
+        where
+            R: TypeConstructor,
+            $param: $bound,
+        {
+            select: Arc<
+                dyn for<'a, 'store> Fn(&'a R::Type<'store>, ReadContext<'store>) -> $output
+                    + Send
+                    + Sync,
+            >,
+        }
+
+        $(#[$boxed_attr])*
+        pub type $boxed<R, $param> = Box<
+            dyn for<'a, 'store> Fn(&'a <R as TypeConstructor>::Type<'store>, ReadContext<'store>)
+                -> $output
+                + Send
+                + Sync,
+        >;
+
+        impl<R, $param> $selector<R, $param>
+        where
+            R: TypeConstructor + 'static,
+            $param: $bound + 'static,
+        {
+            fn from_fn<F>(f: F) -> Self
+            where
+                F: for<'a, 'store> Fn(&'a R::Type<'store>, ReadContext<'store>) -> $output
+                    + Send
+                    + Sync
+                    + 'static,
+            {
+                $selector {
+                    select: Arc::new(f),
+                }
+            }
+
+            /// Selects the value from the given `root`.
+            pub fn select<'a, 'store>(
+                &self,
+                root: &'a R::Type<'store>,
+                cx: ReadContext<'store>,
+            ) -> $output {
+                (self.select)(root, cx)
+            }
+
+            /// Returns a boxed function that selects the value, which can be used as the selector
+            /// for a memo.
+            pub fn to_fn(&self) -> $boxed<R, $param> {
+                let select = self.select.clone();
+
+                Box::new(move |root, cx| select(root, cx))
+            }
+
+            /// Returns a memo for the selected value.
+            pub fn memo(&self, store: &Store<R>) -> $memo {
+                <$memo>::new(store, self.to_fn())
+            }
+        }
+
+        impl<R, $param> Clone for $selector<R, $param>
+        where
+            R: TypeConstructor,
+            $param: $bound,
+        {
+            fn clone(&self) -> Self {
+                $selector {
+                    select: self.select.clone(),
+                }
+            }
+        }
+
+        impl<R, N> NodeSelector<R, N>
+        where
+            R: TypeConstructor + 'static,
+            N: TypeConstructor + 'static,
+        {
+            $(#[$map_attr])*
+            pub fn $map<$param, F>(&self, f: F) -> $selector<R, $param>
+            where
+                $param: $bound + 'static,
+                F: for<'a, 'store> Fn(&'a N::Type<'store>, ReadContext<'store>) -> $output
+                    + Send
+                    + Sync
+                    + 'static,
+            {
+                let select = self.select.clone();
+
+                $selector::from_fn(move |root, cx| f(select(root, cx), cx))
+            }
+        }
+    };
+}
+
+selector!(
+    /// Selects a [VersionedCell] in the data graph of a store with root type constructor `R`.
+    CellSelector<T: 'static>,
+    /// Boxed selector function for a [CellMemo], see [CellSelector::to_fn].
+    CellSelectFn,
+    /// Returns a selector that selects the cell that `f` selects from the node selected by this
+    /// selector.
+    cell,
+    CellMemo<R, CellSelectFn<R, T>>,
+    &'a VersionedCell<'store, T>
+);
+
+selector!(
+    /// Selects a [VersionedCell] that contains a node in the data graph of a store with root type
+    /// constructor `R`.
+    NodeCellSelector<M: TypeConstructor>,
+    /// Boxed selector function for a [NodeMemo], see [NodeCellSelector::to_fn].
+    NodeCellSelectFn,
+    /// Returns a selector that selects the node cell that `f` selects from the node selected by
+    /// this selector.
+    node_cell,
+    NodeMemo<M, R, NodeCellSelectFn<R, M>>,
+    &'a VersionedCell<'store, <M as TypeConstructor>::Type<'store>>
+);
+
+selector!(
+    /// Selects an optional [VersionedCell] in the data graph of a store with root type constructor
+    /// `R`.
+    OptionCellSelector<T: 'static>,
+    /// Boxed selector function for an [OptionCellMemo], see [OptionCellSelector::to_fn].
+    OptionCellSelectFn,
+    /// Returns a selector that selects the optional cell that `f` selects from the node selected
+    /// by this selector.
+    option_cell,
+    OptionCellMemo<R, OptionCellSelectFn<R, T>>,
+    Option<&'a VersionedCell<'store, T>>
+);
+
+selector!(
+    /// Selects an optional [VersionedCell] that contains a node in the data graph of a store with
+    /// root type constructor `R`.
+    OptionNodeCellSelector<M: TypeConstructor>,
+    /// Boxed selector function for an [OptionNodeMemo], see [OptionNodeCellSelector::to_fn].
+    OptionNodeCellSelectFn,
+    /// Returns a selector that selects the optional node cell that `f` selects from the node
+    /// selected by this selector.
+    option_node_cell,
+    OptionNodeMemo<M, R, OptionNodeCellSelectFn<R, M>>,
+    Option<&'a VersionedCell<'store, <M as TypeConstructor>::Type<'store>>>
+);
+
+selector!(
+    /// Selects a slice of [VersionedCell]s in the data graph of a store with root type constructor
+    /// `R`.
+    CellSliceSelector<T: 'static>,
+    /// Boxed selector function for a [CellSliceMemo], see [CellSliceSelector::to_fn].
+    CellSliceSelectFn,
+    /// Returns a selector that selects the slice of cells that `f` selects from the node selected
+    /// by this selector.
+    cell_slice,
+    CellSliceMemo<R, CellSliceSelectFn<R, T>>,
+    &'a [VersionedCell<'store, T>]
+);
+
+selector!(
+    /// Selects a slice of [VersionedCell]s that contain nodes in the data graph of a store with
+    /// root type constructor `R`.
+    NodeSliceSelector<M: TypeConstructor>,
+    /// Boxed selector function for a [NodeSliceMemo], see [NodeSliceSelector::to_fn].
+    NodeSliceSelectFn,
+    /// Returns a selector that selects the slice of node cells that `f` selects from the node
+    /// selected by this selector.
+    node_slice,
+    NodeSliceMemo<M, R, NodeSliceSelectFn<R, M>>,
+    &'a [VersionedCell<'store, <M as TypeConstructor>::Type<'store>>]
+);
+
+impl<R, M> NodeCellSelector<R, M>
+where
+    R: TypeConstructor + 'static,
+    M: TypeConstructor + 'static,
+{
+    /// Returns a selector that selects the node inside the cell selected by this selector.
+    ///
+    /// Use this to select the node's cells.
+    pub fn node(&self) -> NodeSelector<R, M> {
+        let select = self.select.clone();
+
+        NodeSelector::from_fn(move |root, cx| {
+            let cell = select(root, cx);
+
+            cx.track(cell);
+
+            // Note: we cannot use `VersionedCell::deref` here: the compiler cannot derive that the
+            // store lifetime outlives the borrow of the root from the root's (projected) type.
+            //
+            // SAFETY: the cell is part of the data graph of the root, for which the `ReadContext`
+            // guarantees that no mutable references exist for the lifetime of the borrow.
+            unsafe { &*VersionedCell::value_ptr(cell) }
+        })
+    }
+}
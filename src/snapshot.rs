@@ -0,0 +1,574 @@
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::hash::{BuildHasher, Hash};
+use std::marker;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::store::ReadContext;
+use crate::versioned_cell::{CellFree, VersionedCell};
+use crate::TypeConstructor;
+
+/// Immutable point-in-time copy of the data in a [Store](crate::store::Store).
+///
+/// See [Store::snapshot](crate::store::Store::snapshot). Cloning a [Snapshot] is cheap: clones
+/// share the same data (as does the store, until its next update scope).
+pub struct Snapshot<C>
+where
+    C: TypeConstructor,
+{
+    data: Arc<<C as TypeConstructor>::Type<'static>>,
+    store_id: usize,
+    version: u64,
+}
+
+impl<C> Snapshot<C>
+where
+    C: TypeConstructor,
+{
+    pub(crate) fn new(
+        data: Arc<<C as TypeConstructor>::Type<'static>>,
+        store_id: usize,
+        version: u64,
+    ) -> Self {
+        Snapshot {
+            data,
+            store_id,
+            version,
+        }
+    }
+
+    /// Returns the ID of the store from which this snapshot was taken.
+    pub fn store_id(&self) -> usize {
+        self.store_id
+    }
+
+    /// Returns the version the store had when this snapshot was taken, see
+    /// [Store::version](crate::store::Store::version).
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Runs a read scope for the snapshot's data.
+    ///
+    /// The [ReadContext] passed to `f` is associated with the store from which this snapshot was
+    /// taken.
+    pub fn with<F, O>(&self, f: F) -> O
+    where
+        F: for<'store> FnOnce(&<C as TypeConstructor>::Type<'store>, ReadContext<'store>) -> O,
+    {
+        unsafe {
+            f(
+                ::std::mem::transmute::<
+                    &<C as TypeConstructor>::Type<'static>,
+                    &<C as TypeConstructor>::Type<'_>,
+                >(&*self.data),
+                ReadContext::new(self.store_id, self.version),
+            )
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<C> serde::Serialize for Snapshot<C>
+where
+    C: TypeConstructor,
+    for<'store> <C as TypeConstructor>::Type<'store>: serde::Serialize,
+{
+    /// Serializes the snapshot's root.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.data.serialize(serializer)
+    }
+}
+
+impl<C> Clone for Snapshot<C>
+where
+    C: TypeConstructor,
+{
+    fn clone(&self) -> Self {
+        Snapshot {
+            data: self.data.clone(),
+            store_id: self.store_id,
+            version: self.version,
+        }
+    }
+}
+
+/// A type whose values can be copied into a [Snapshot].
+///
+/// Copying a [VersionedCell](crate::versioned_cell::VersionedCell) copies its version along with
+/// its value. Cell versions must be unique within a store, so a cell and its copy must never both
+/// be part of the store. Copying therefore requires a [SnapshotToken], which only the store can
+/// create, when it separates its data from a snapshot (see
+/// [Store::snapshot](crate::store::Store::snapshot)).
+///
+/// Implemented for cells, the collections in [crate::collections], and common standard library
+/// types. With the `derive` feature, `#[derive(SnapshotClone)]` implements it for structs and
+/// enums whose fields implement it; a field of a type that only implements [Clone] (and does not
+/// own any cells, see [CellFree]) can be marked with `#[snapshot(clone)]`.
+///
+/// A snapshot never shares cells with its store: [Arc] and [Rc] share their value with the
+/// snapshot rather than copying it, so they only implement [SnapshotClone] if their value does not
+/// own any cells:
+///
+/// ```compile_fail,E0277
+/// # use std::sync::Arc;
+/// # use viemo::snapshot::SnapshotClone;
+/// # use viemo::versioned_cell::VersionedCell;
+/// fn assert_snapshot_clone<T: SnapshotClone>() {}
+///
+/// assert_snapshot_clone::<Arc<VersionedCell<'static, u32>>>();
+/// ```
+///
+/// # Example
+///
+/// ```ignore
+/// #[derive(TypeConstructor, SnapshotClone)]
+/// struct Root<'store> {
+///     name: VersionedCell<'store, String>,
+///     #[snapshot(clone)]
+///     settings: Settings,
+/// }
+///
+/// let snapshot = store.snapshot();
+/// ```
+pub trait SnapshotClone {
+    /// Returns a copy of the value for a snapshot.
+    fn snapshot_clone(&self, token: SnapshotToken<'_>) -> Self;
+
+    /// Records the cells owned by the value in the `map`, along with the corresponding cells in
+    /// `copy`, a copy of the value returned by [Self::snapshot_clone].
+    ///
+    /// A store's first update scope after a snapshot was taken moves the store to a copy of its
+    /// data (see [Store::snapshot](crate::store::Store::snapshot)); the map is used to move the
+    /// store's undo history along with it. Values that own cells should forward to the
+    /// corresponding fields of `copy`. Cells that are not recorded cannot be found in the copy,
+    /// so the store clears its undo history instead; the default implementation records nothing,
+    /// which is only correct for values that do not own any cells.
+    fn map_cells<'a, 'b>(&'a self, _copy: &'b Self, _map: &mut CellMap<'a, 'b>) {}
+}
+
+/// Maps the cells of a value to the corresponding cells of its copy, see
+/// [SnapshotClone::map_cells].
+///
+/// A cell can only be recorded along with a cell that lives as long as the copy, so the map cannot
+/// refer to cells that are dropped before the copy.
+pub struct CellMap<'a, 'b> {
+    cells: HashMap<usize, usize>,
+    _marker: marker::PhantomData<(Cell<&'a ()>, Cell<&'b ()>)>,
+}
+
+impl<'a, 'b> CellMap<'a, 'b> {
+    pub(crate) fn new() -> Self {
+        CellMap {
+            cells: HashMap::new(),
+            _marker: marker::PhantomData,
+        }
+    }
+
+    pub(crate) fn insert<T>(
+        &mut self,
+        cell: &'a VersionedCell<'_, T>,
+        copy: &'b VersionedCell<'_, T>,
+    ) {
+        self.cells.insert(
+            cell as *const VersionedCell<'_, T> as usize,
+            copy as *const VersionedCell<'_, T> as usize,
+        );
+    }
+
+    /// Records that the cell at the given `address` is not part of the value, and therefore stays
+    /// where it is.
+    pub(crate) fn keep(&mut self, address: usize) {
+        self.cells.insert(address, address);
+    }
+
+    /// Returns the address of the copy of the cell at the given `address`, if it was recorded.
+    pub(crate) fn get(&self, address: usize) -> Option<usize> {
+        self.cells.get(&address).copied()
+    }
+}
+
+/// Proof that a [Snapshot] is being taken, see [SnapshotClone].
+///
+/// A token cannot be created outside of the store (see
+/// [Store::snapshot](crate::store::Store::snapshot)), so cells cannot be copied inside an update
+/// scope:
+///
+/// ```compile_fail,E0624
+/// # use viemo::snapshot::{SnapshotClone, SnapshotToken};
+/// # use viemo::store::UpdateContext;
+/// # use viemo::versioned_cell::VersionedCell;
+/// fn copy<'store>(cell: &VersionedCell<'store, u32>, cx: UpdateContext<'store>) {
+///     let copies = vec![cell.snapshot_clone(SnapshotToken::new())];
+/// }
+/// ```
+#[derive(Clone, Copy)]
+pub struct SnapshotToken<'a> {
+    _marker: marker::PhantomData<Cell<&'a ()>>,
+}
+
+impl SnapshotToken<'_> {
+    pub(crate) fn new() -> Self {
+        SnapshotToken {
+            _marker: marker::PhantomData,
+        }
+    }
+}
+
+macro_rules! impl_snapshot_clone_by_clone {
+    ($($tpe:ty),*) => {
+        $(
+            impl SnapshotClone for $tpe {
+                fn snapshot_clone(&self, _token: SnapshotToken<'_>) -> Self {
+                    self.clone()
+                }
+            }
+        )*
+    };
+}
+
+impl_snapshot_clone_by_clone!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    String,
+    &'static str
+);
+
+impl<T: ?Sized> SnapshotClone for marker::PhantomData<T> {
+    fn snapshot_clone(&self, _token: SnapshotToken<'_>) -> Self {
+        marker::PhantomData
+    }
+}
+
+/// Shares the value with the snapshot, rather than copying it.
+///
+/// Only implemented for values that do not own any cells, as the snapshot would otherwise share
+/// those cells with the store.
+impl<T: ?Sized + CellFree> SnapshotClone for Arc<T> {
+    fn snapshot_clone(&self, _token: SnapshotToken<'_>) -> Self {
+        self.clone()
+    }
+}
+
+/// Shares the value with the snapshot, rather than copying it.
+///
+/// Only implemented for values that do not own any cells, see the implementation for [Arc].
+impl<T: ?Sized + CellFree> SnapshotClone for Rc<T> {
+    fn snapshot_clone(&self, _token: SnapshotToken<'_>) -> Self {
+        self.clone()
+    }
+}
+
+impl<T: SnapshotClone> SnapshotClone for Box<T> {
+    fn snapshot_clone(&self, token: SnapshotToken<'_>) -> Self {
+        Box::new((**self).snapshot_clone(token))
+    }
+
+    fn map_cells<'a, 'b>(&'a self, copy: &'b Self, map: &mut CellMap<'a, 'b>) {
+        (**self).map_cells(&**copy, map);
+    }
+}
+
+impl<T: SnapshotClone> SnapshotClone for Option<T> {
+    fn snapshot_clone(&self, token: SnapshotToken<'_>) -> Self {
+        self.as_ref().map(|value| value.snapshot_clone(token))
+    }
+
+    fn map_cells<'a, 'b>(&'a self, copy: &'b Self, map: &mut CellMap<'a, 'b>) {
+        if let (Some(value), Some(copy)) = (self, copy) {
+            value.map_cells(copy, map);
+        }
+    }
+}
+
+impl<T: SnapshotClone, E: SnapshotClone> SnapshotClone for Result<T, E> {
+    fn snapshot_clone(&self, token: SnapshotToken<'_>) -> Self {
+        match self {
+            Ok(value) => Ok(value.snapshot_clone(token)),
+            Err(error) => Err(error.snapshot_clone(token)),
+        }
+    }
+
+    fn map_cells<'a, 'b>(&'a self, copy: &'b Self, map: &mut CellMap<'a, 'b>) {
+        match (self, copy) {
+            (Ok(value), Ok(copy)) => value.map_cells(copy, map),
+            (Err(error), Err(copy)) => error.map_cells(copy, map),
+            _ => (),
+        }
+    }
+}
+
+impl<T: SnapshotClone, const N: usize> SnapshotClone for [T; N] {
+    fn snapshot_clone(&self, token: SnapshotToken<'_>) -> Self {
+        std::array::from_fn(|index| self[index].snapshot_clone(token))
+    }
+
+    fn map_cells<'a, 'b>(&'a self, copy: &'b Self, map: &mut CellMap<'a, 'b>) {
+        for (value, copy) in self.iter().zip(copy) {
+            value.map_cells(copy, map);
+        }
+    }
+}
+
+impl<T: SnapshotClone> SnapshotClone for Vec<T> {
+    fn snapshot_clone(&self, token: SnapshotToken<'_>) -> Self {
+        self.iter()
+            .map(|value| value.snapshot_clone(token))
+            .collect()
+    }
+
+    fn map_cells<'a, 'b>(&'a self, copy: &'b Self, map: &mut CellMap<'a, 'b>) {
+        for (value, copy) in self.iter().zip(copy) {
+            value.map_cells(copy, map);
+        }
+    }
+}
+
+impl<T: SnapshotClone> SnapshotClone for VecDeque<T> {
+    fn snapshot_clone(&self, token: SnapshotToken<'_>) -> Self {
+        self.iter()
+            .map(|value| value.snapshot_clone(token))
+            .collect()
+    }
+
+    fn map_cells<'a, 'b>(&'a self, copy: &'b Self, map: &mut CellMap<'a, 'b>) {
+        for (value, copy) in self.iter().zip(copy) {
+            value.map_cells(copy, map);
+        }
+    }
+}
+
+impl<K, V, S> SnapshotClone for HashMap<K, V, S>
+where
+    K: Clone + Eq + Hash,
+    V: SnapshotClone,
+    S: Clone + BuildHasher,
+{
+    fn snapshot_clone(&self, token: SnapshotToken<'_>) -> Self {
+        let mut map = HashMap::with_capacity_and_hasher(self.len(), self.hasher().clone());
+
+        map.extend(
+            self.iter()
+                .map(|(key, value)| (key.clone(), value.snapshot_clone(token))),
+        );
+
+        map
+    }
+
+    fn map_cells<'a, 'b>(&'a self, copy: &'b Self, map: &mut CellMap<'a, 'b>) {
+        for (key, value) in self {
+            if let Some(copy) = copy.get(key) {
+                value.map_cells(copy, map);
+            }
+        }
+    }
+}
+
+impl<K: Clone + Ord, V: SnapshotClone> SnapshotClone for BTreeMap<K, V> {
+    fn snapshot_clone(&self, token: SnapshotToken<'_>) -> Self {
+        self.iter()
+            .map(|(key, value)| (key.clone(), value.snapshot_clone(token)))
+            .collect()
+    }
+
+    fn map_cells<'a, 'b>(&'a self, copy: &'b Self, map: &mut CellMap<'a, 'b>) {
+        for (value, copy) in self.values().zip(copy.values()) {
+            value.map_cells(copy, map);
+        }
+    }
+}
+
+macro_rules! impl_snapshot_clone_tuple {
+    ($($param:ident $index:tt),*) => {
+        impl<$($param: SnapshotClone),*> SnapshotClone for ($($param,)*) {
+            fn snapshot_clone(&self, token: SnapshotToken<'_>) -> Self {
+                ($(self.$index.snapshot_clone(token),)*)
+            }
+
+            fn map_cells<'a, 'b>(&'a self, copy: &'b Self, map: &mut CellMap<'a, 'b>) {
+                $(self.$index.map_cells(&copy.$index, map);)*
+            }
+        }
+    };
+}
+
+impl_snapshot_clone_tuple!(A 0);
+impl_snapshot_clone_tuple!(A 0, B 1);
+impl_snapshot_clone_tuple!(A 0, B 1, C 2);
+impl_snapshot_clone_tuple!(A 0, B 1, C 2, D 3);
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{CellMap, SnapshotClone, SnapshotToken};
+    use crate::collections::VersionedVec;
+    use crate::store::Store;
+    use crate::versioned_cell::VersionedCell;
+
+    struct Root<'store> {
+        a: VersionedCell<'store, u32>,
+        b: VersionedVec<'store, String>,
+        c: Arc<String>,
+    }
+
+    impl SnapshotClone for Root<'_> {
+        fn snapshot_clone(&self, token: SnapshotToken<'_>) -> Self {
+            Root {
+                a: self.a.snapshot_clone(token),
+                b: self.b.snapshot_clone(token),
+                c: self.c.snapshot_clone(token),
+            }
+        }
+
+        fn map_cells<'a, 'b>(&'a self, copy: &'b Self, map: &mut CellMap<'a, 'b>) {
+            self.a.map_cells(&copy.a, map);
+            self.b.map_cells(&copy.b, map);
+        }
+    }
+
+    crate::gen_type_constructor!(Root, RootTC);
+
+    fn store() -> Store<RootTC> {
+        Store::<RootTC>::initialize(|cx| Root {
+            a: VersionedCell::new(cx, 1),
+            b: VersionedVec::new(cx),
+            c: Arc::new(String::from("c")),
+        })
+    }
+
+    fn address(root: &Root) -> usize {
+        &root.a as *const VersionedCell<u32> as usize
+    }
+
+    #[test]
+    fn snapshot_keeps_values_and_versions() {
+        let store = Store::<RootTC>::initialize(|cx| Root {
+            a: VersionedCell::new(cx, 1),
+            b: VersionedVec::from_vec(cx, vec![String::from("b")]),
+            c: Arc::new(String::from("c")),
+        });
+
+        let snapshot = store.snapshot();
+        let versions = store.with(|root, _| (root.a.version(), root.b.structure_version()));
+
+        store.update(|root, cx| {
+            *root.a.borrow_mut(cx) = 2;
+            root.b.push(cx, String::from("c"));
+        });
+
+        snapshot.with(|root, cx| {
+            assert_eq!(*root.a.deref(cx), 1);
+            assert_eq!(root.b.len(cx), 1);
+            assert_eq!((root.a.version(), root.b.structure_version()), versions);
+        });
+
+        let shared = store.with(|root, _| root.c.clone());
+
+        snapshot.with(|root, _| assert!(Arc::ptr_eq(&root.c, &shared)));
+    }
+
+    #[test]
+    fn snapshot_shares_data_until_update() {
+        let store = store();
+        let snapshot = store.snapshot();
+
+        assert_eq!(
+            store.with(|root, _| address(root)),
+            snapshot.with(|root, _| address(root))
+        );
+
+        store.update(|root, cx| *root.a.borrow_mut(cx) = 2);
+
+        assert_ne!(
+            store.with(|root, _| address(root)),
+            snapshot.with(|root, _| address(root))
+        );
+        assert_eq!(snapshot.with(|root, cx| *root.a.deref(cx)), 1);
+    }
+
+    #[test]
+    fn undo_after_snapshot() {
+        let store = store();
+
+        store.set_history_depth(10);
+        store.update(|root, cx| *root.a.borrow_mut(cx) = 2);
+
+        let snapshot = store.snapshot();
+
+        store.update(|root, cx| *root.a.borrow_mut(cx) = 3);
+
+        let snapshot_after_update = store.snapshot();
+
+        assert!(store.undo());
+        assert_eq!(store.with(|root, cx| *root.a.deref(cx)), 2);
+        assert!(store.undo());
+        assert_eq!(store.with(|root, cx| *root.a.deref(cx)), 1);
+        assert!(store.redo());
+        assert_eq!(store.with(|root, cx| *root.a.deref(cx)), 2);
+
+        assert_eq!(snapshot.with(|root, cx| *root.a.deref(cx)), 2);
+        assert_eq!(snapshot_after_update.with(|root, cx| *root.a.deref(cx)), 3);
+    }
+
+    #[test]
+    fn undo_structural_change_after_snapshot() {
+        let store = store();
+
+        store.set_history_depth(10);
+        store.update(|root, cx| root.b.push(cx, String::from("b")));
+        store.update(|root, cx| {
+            root.b.borrow(cx)[0].borrow_mut(cx).push('c');
+            root.b.clear(cx);
+        });
+
+        let snapshot = store.snapshot();
+
+        assert!(store.undo());
+        assert_eq!(
+            store.with(|root, cx| root.b.get(0, cx).unwrap().deref(cx).clone()),
+            "b"
+        );
+        assert!(store.undo());
+        assert_eq!(store.with(|root, cx| root.b.len(cx)), 0);
+        assert!(store.redo());
+        assert_eq!(store.with(|root, cx| root.b.len(cx)), 1);
+
+        assert_eq!(snapshot.with(|root, cx| root.b.len(cx)), 0);
+    }
+
+    #[test]
+    fn rollback_after_snapshot() {
+        let store = store();
+        let snapshot = store.snapshot();
+
+        let result: Result<(), ()> = store.try_update(|root, cx| {
+            *root.a.borrow_mut(cx) = 2;
+
+            Err(())
+        });
+
+        assert_eq!(result, Err(()));
+        assert_eq!(store.with(|root, cx| *root.a.deref(cx)), 1);
+        assert_eq!(snapshot.with(|root, cx| *root.a.deref(cx)), 1);
+    }
+}
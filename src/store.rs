@@ -1,26 +1,118 @@
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::future::Future;
 use std::marker;
+use std::ops::{Deref, DerefMut};
+use std::panic::{self, AssertUnwindSafe};
 use std::pin::Pin;
-use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{
+    Arc, Mutex, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError, Weak,
+};
 use std::task::{Context, Poll, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+use std::{error, fmt};
 
 use atomic_counter::{AtomicCounter, RelaxedCounter};
+use futures::task::{self, ArcWake};
 use futures::Stream;
 use lazy_static::lazy_static;
 
 use crate::broadcast::{Broadcaster, Listener};
+use crate::history::History;
+use crate::journal::{Journal, Structure};
+use crate::snapshot::{CellMap, Snapshot, SnapshotClone, SnapshotToken};
+use crate::tracker::Tracker;
+use crate::versioned_cell::{CellStable, VersionedCell};
 use crate::TypeConstructor;
 
 lazy_static! {
     static ref STORE_ID_PROVIDER: RelaxedCounter = RelaxedCounter::new(0);
 }
 
+thread_local! {
+    // The scopes the current thread holds, along with the IDs of their stores, in the order in
+    // which they were entered.
+    static ACTIVE_SCOPES: RefCell<Vec<(usize, ActiveScope)>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Clone, Copy)]
+enum ActiveScope {
+    // Points to the store's `Shared` data.
+    Read(*const ()),
+    // The write lock is held, but no update scope is running (yet).
+    Write,
+    Update {
+        // Points to the store's root.
+        data: *const (),
+        provider: *mut UpdateContextProvider,
+    },
+}
+
+/// Replaces the most recently entered scope for the store with the given `store_id`.
+fn set_active_scope(store_id: usize, scope: ActiveScope) {
+    ACTIVE_SCOPES.with(|scopes| {
+        if let Some((_, active)) = scopes
+            .borrow_mut()
+            .iter_mut()
+            .rev()
+            .find(|(id, _)| *id == store_id)
+        {
+            *active = scope;
+        }
+    });
+}
+
 struct Shared<C>
 where
     C: TypeConstructor,
 {
-    data: <C as TypeConstructor>::Type<'static>,
+    // Shared with the snapshots taken since the most recent update scope, see `Store::snapshot`.
+    data: Arc<<C as TypeConstructor>::Type<'static>>,
+    // Set by the first snapshot (which has the `SnapshotClone` bound), see `Shared::unshare`.
+    copy_data: OnceLock<fn(&mut Shared<C>)>,
     update_context_provider: UpdateContextProvider,
+    history: History,
+    panic_policy: PanicPolicy,
+}
+
+impl<C> Shared<C>
+where
+    C: TypeConstructor,
+{
+    /// Replaces the data with a copy if it is shared with a snapshot, so that it can be modified
+    /// without affecting the snapshot.
+    fn unshare(&mut self) {
+        if Arc::get_mut(&mut self.data).is_none() {
+            let copy_data = *self
+                .copy_data
+                .get()
+                .expect("the data is only shared after taking a snapshot");
+
+            copy_data(self);
+        }
+    }
+}
+
+/// Replaces the `shared` data with a copy, see `Shared::unshare`.
+fn copy_data<C>(shared: &mut Shared<C>)
+where
+    C: TypeConstructor,
+    for<'store> <C as TypeConstructor>::Type<'store>: SnapshotClone,
+{
+    let copy = Arc::new(shared.data.snapshot_clone(SnapshotToken::new()));
+
+    // The history refers to the cells in the data, which from now on only belong to the snapshots.
+    if shared.history.can_undo() || shared.history.can_redo() {
+        let mut map = CellMap::new();
+
+        shared.data.map_cells(&copy, &mut map);
+        shared.history.rebase(&mut map);
+    }
+
+    shared.data = copy;
 }
 
 struct Lock<C>
@@ -29,27 +121,570 @@ where
 {
     shared: RwLock<Shared<C>>,
     store_id: usize,
+    // The store's version as of the end of the most recent update scope or undo, which can be read
+    // without acquiring the lock, see `Store::version`.
+    version: AtomicU64,
+    // Tasks waiting for the lock to become available, see `Acquire`.
+    waiters: Broadcaster<Mutex<Option<Waker>>>,
 }
 
 impl<C> Lock<C>
 where
     C: TypeConstructor,
 {
+    /// Returns the kind of scope the current thread holds for this store, if any.
+    fn active_scope(&self) -> Option<ActiveScope> {
+        ACTIVE_SCOPES.with(|scopes| {
+            scopes
+                .borrow()
+                .iter()
+                .rev()
+                .find(|(store_id, _)| *store_id == self.store_id)
+                .map(|(_, scope)| *scope)
+        })
+    }
+
+    /// Returns a read guard that joins the read scope the current thread holds for this store, if
+    /// any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the current thread holds an update scope for this store.
+    fn join_read(&self) -> Option<ReadGuard<'_, C>> {
+        match self.active_scope() {
+            // SAFETY: the outer read guard outlives the nested guard, as scopes on a thread end in
+            // reverse order.
+            Some(ActiveScope::Read(shared)) => Some(ReadGuard::nested(
+                unsafe { &*(shared as *const Shared<C>) },
+                self,
+            )),
+            Some(_) => panic!(
+                "cannot read store {} inside an update scope for the same store on the same \
+                thread; this would deadlock",
+                self.store_id
+            ),
+            None => None,
+        }
+    }
+
+    /// Panics if the current thread holds a read or update scope for this store.
+    fn assert_unlocked(&self) {
+        match self.active_scope() {
+            Some(ActiveScope::Read(_)) => panic!(
+                "cannot update store {} inside a read scope for the same store on the same \
+                thread; this would deadlock",
+                self.store_id
+            ),
+            Some(_) => panic!(
+                "cannot lock store {} inside an update scope for the same store on the same \
+                thread; this would deadlock",
+                self.store_id
+            ),
+            None => (),
+        }
+    }
+
+    fn read(&self) -> ReadGuard<'_, C> {
+        if let Some(guard) = self.join_read() {
+            return guard;
+        }
+
+        ReadGuard::new(self.shared.read().expect("poisoned"), self)
+    }
+
+    fn write(&self) -> WriteGuard<'_, C> {
+        self.assert_unlocked();
+
+        WriteGuard::new(self.shared.write().expect("poisoned"), self)
+    }
+
+    fn try_read(&self) -> Option<ReadGuard<'_, C>> {
+        if let Some(guard) = self.join_read() {
+            return Some(guard);
+        }
+
+        match self.shared.try_read() {
+            Ok(guard) => Some(ReadGuard::new(guard, self)),
+            Err(TryLockError::WouldBlock) => None,
+            Err(TryLockError::Poisoned(_)) => panic!("poisoned"),
+        }
+    }
+
+    fn try_write(&self) -> Option<WriteGuard<'_, C>> {
+        self.assert_unlocked();
+
+        match self.shared.try_write() {
+            Ok(guard) => Some(WriteGuard::new(guard, self)),
+            Err(TryLockError::WouldBlock) => None,
+            Err(TryLockError::Poisoned(_)) => panic!("poisoned"),
+        }
+    }
+
+    /// Blocks the current thread until a read guard can be acquired, or until the `deadline`
+    /// passes.
+    fn read_until(&self, deadline: Option<Instant>) -> Option<ReadGuard<'_, C>> {
+        self.read_async().wait_until(deadline)
+    }
+
+    /// Blocks the current thread until a write guard can be acquired, or until the `deadline`
+    /// passes.
+    fn write_until(&self, deadline: Option<Instant>) -> Option<WriteGuard<'_, C>> {
+        self.write_async().wait_until(deadline)
+    }
+
+    /// Returns a future that resolves to a read guard without blocking the current thread.
+    fn read_async(&self) -> Acquire<'_, C, ReadGuard<'_, C>> {
+        Acquire::new(self, Lock::try_read)
+    }
+
+    /// Returns a future that resolves to a write guard without blocking the current thread.
+    fn write_async(&self) -> Acquire<'_, C, WriteGuard<'_, C>> {
+        Acquire::new(self, Lock::try_write)
+    }
+
+    /// Wakes all tasks waiting for the lock to become available.
+    fn notify_released(&self) {
+        self.waiters.broadcast(|waker| {
+            if let Some(waker) = waker.lock().unwrap().take() {
+                waker.wake();
+            }
+        });
+    }
+
     fn with<F, R>(&self, f: F) -> R
     where
         F: for<'store> FnOnce(&<C as TypeConstructor>::Type<'store>, ReadContext<'store>) -> R,
     {
-        let lock = self.shared.read().expect("poisoned");
+        self.read_scope(self.read(), f)
+    }
+
+    /// Runs a read scope for `f` while holding the `lock`.
+    fn read_scope<F, R>(&self, lock: ReadGuard<'_, C>, f: F) -> R
+    where
+        F: for<'store> FnOnce(&<C as TypeConstructor>::Type<'store>, ReadContext<'store>) -> R,
+    {
+        let version = lock.update_context_provider.next_version;
 
         unsafe {
             f(
-                ::std::mem::transmute::<&<C as TypeConstructor>::Type<'static>, _>(&lock.data),
-                ReadContext::new(self.store_id),
+                ::std::mem::transmute::<&<C as TypeConstructor>::Type<'static>, _>(&*lock.data),
+                ReadContext::new(self.store_id, version),
+            )
+        }
+    }
+
+    /// Runs an update scope for `f`.
+    ///
+    /// If `strict` is `true`, the scope's changes are recorded into a strict journal, so that they
+    /// can be rolled back. Otherwise, they are only recorded into a lenient journal (see
+    /// `Journal::lenient`) if the history is enabled or the panic policy is
+    /// [PanicPolicy::Rollback].
+    ///
+    /// If `f` panics, the panic is caught, and the status's value holds the panic's payload. If the
+    /// panic policy is [PanicPolicy::Rollback] (or the journal is strict) and the journal is
+    /// restorable, the scope's changes are rolled back. The lock is released without being
+    /// poisoned either way. The status of a scope that was rolled back never reports a change,
+    /// while a scope that panicked but kept its changes does, so that update listeners observe
+    /// them.
+    fn update<F, R>(&self, strict: bool, f: F) -> UpdateStatus<thread::Result<R>>
+    where
+        F: for<'store> FnOnce(&<C as TypeConstructor>::Type<'store>, UpdateContext<'store>) -> R,
+    {
+        self.update_scope(self.write(), strict, f)
+    }
+
+    /// Runs an update scope for `f` while holding the `lock`, see [update].
+    fn update_scope<F, R>(
+        &self,
+        mut lock: WriteGuard<'_, C>,
+        strict: bool,
+        f: F,
+    ) -> UpdateStatus<thread::Result<R>>
+    where
+        F: for<'store> FnOnce(&<C as TypeConstructor>::Type<'store>, UpdateContext<'store>) -> R,
+    {
+        lock.unshare();
+
+        let Shared {
+            data,
+            update_context_provider,
+            history,
+            panic_policy,
+            ..
+        } = &mut *lock;
+
+        let initial_version = update_context_provider.next_version;
+        let rollback_on_panic = strict || *panic_policy == PanicPolicy::Rollback;
+
+        update_context_provider.journal = if strict {
+            Some(Journal::strict(initial_version))
+        } else if rollback_on_panic || history.is_enabled() {
+            Some(Journal::lenient(initial_version))
+        } else {
+            None
+        };
+
+        let context = unsafe { update_context_provider.update_context() };
+        let data: &<C as TypeConstructor>::Type<'static> = data;
+
+        // Allow update scopes that start inside `f` to join this scope, see `join_update`.
+        set_active_scope(
+            self.store_id,
+            ActiveScope::Update {
+                data: data as *const _ as *const (),
+                provider: context.provider,
+            },
+        );
+
+        // Catching the panic here (rather than letting it unwind through the write guard) keeps the
+        // lock from being poisoned. Any `Ref`s and `RefMut`s created by `f` are dropped while
+        // unwinding, which resets the borrow flags of the cells they borrowed.
+        let value = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+            f(
+                ::std::mem::transmute::<&<C as TypeConstructor>::Type<'static>, _>(data),
+                context,
             )
+        }));
+
+        let mut is_rolled_back = false;
+
+        if let Some(journal) = update_context_provider.journal.take() {
+            if value.is_err() && rollback_on_panic && journal.is_restorable() {
+                // SAFETY: any borrows created by `f` have ended by the time it returns or unwinds.
+                unsafe {
+                    journal.rollback();
+                }
+
+                is_rolled_back = true;
+            } else if history.is_enabled() {
+                history.record(journal);
+            }
+        }
+
+        self.version
+            .store(update_context_provider.next_version, Ordering::Release);
+
+        UpdateStatus {
+            is_changed: !is_rolled_back && update_context_provider.next_version != initial_version,
+            value,
+            version: update_context_provider.next_version,
+        }
+    }
+
+    /// Runs `f` as part of the update scope the current thread holds for this store, if any.
+    ///
+    /// Returns `f` if the current thread does not hold an update scope for this store. Panics that
+    /// occur in `f` are not caught, but propagate to the outer scope.
+    fn join_update<F, R>(&self, f: F) -> Result<UpdateStatus<R>, F>
+    where
+        F: for<'store> FnOnce(&<C as TypeConstructor>::Type<'store>, UpdateContext<'store>) -> R,
+    {
+        let (data, provider) = match self.active_scope() {
+            Some(ActiveScope::Update { data, provider }) => (data, provider),
+            _ => return Err(f),
+        };
+
+        // SAFETY: the outer update scope is still running on this thread, so its data and context
+        // provider are still alive, and no other scope for this store can run concurrently.
+        unsafe {
+            let initial_version = (*provider).next_version;
+
+            let value = f(
+                &*(data as *const <C as TypeConstructor>::Type<'static>),
+                UpdateContext::from_provider(provider),
+            );
+
+            let version = (*provider).next_version;
+
+            Ok(UpdateStatus {
+                value,
+                is_changed: version != initial_version,
+                version,
+            })
         }
     }
+
+    /// Reverts the most recent entry on the undo stack, or the redo stack if `redo` is `true`.
+    ///
+    /// Returns the store's version after the revert, or `None` if the stack was empty.
+    fn undo(&self, redo: bool) -> Option<u64> {
+        let mut lock = self.write();
+
+        let can_revert = if redo {
+            lock.history.can_redo()
+        } else {
+            lock.history.can_undo()
+        };
+
+        if !can_revert {
+            return None;
+        }
+
+        lock.unshare();
+
+        let Shared {
+            update_context_provider,
+            history,
+            ..
+        } = &mut *lock;
+
+        let journal = if redo {
+            history.pop_redo()
+        } else {
+            history.pop_undo()
+        };
+
+        if let Some(journal) = journal {
+            // SAFETY: we hold the write lock, so no cells in the store are borrowed, and journals
+            // in the history are always restorable.
+            let inverse = unsafe { journal.revert(update_context_provider.update_context()) };
+
+            if redo {
+                history.push_undo(inverse);
+            } else {
+                history.push_redo(inverse);
+            }
+
+            self.version
+                .store(update_context_provider.next_version, Ordering::Release);
+
+            Some(update_context_provider.next_version)
+        } else {
+            None
+        }
+    }
+}
+
+/// Notifies the lock's waiters when dropped.
+struct Release<'a, C>
+where
+    C: TypeConstructor,
+{
+    lock: &'a Lock<C>,
+}
+
+impl<'a, C> Release<'a, C>
+where
+    C: TypeConstructor,
+{
+    /// Records the `scope` as active on the current thread until dropped.
+    fn new(lock: &'a Lock<C>, scope: ActiveScope) -> Self {
+        ACTIVE_SCOPES.with(|scopes| scopes.borrow_mut().push((lock.store_id, scope)));
+
+        Release { lock }
+    }
 }
 
+impl<C> Drop for Release<'_, C>
+where
+    C: TypeConstructor,
+{
+    fn drop(&mut self) {
+        let store_id = self.lock.store_id;
+
+        ACTIVE_SCOPES.with(|scopes| {
+            let mut scopes = scopes.borrow_mut();
+
+            if let Some(index) = scopes.iter().rposition(|(id, _)| *id == store_id) {
+                scopes.remove(index);
+            }
+        });
+
+        self.lock.notify_released();
+    }
+}
+
+// Note: fields are dropped in declaration order, so the lock is released before the waiters are
+// notified.
+struct ReadGuard<'a, C>
+where
+    C: TypeConstructor,
+{
+    guard: ReadGuardInner<'a, C>,
+    _release: Release<'a, C>,
+}
+
+enum ReadGuardInner<'a, C>
+where
+    C: TypeConstructor,
+{
+    Locked(RwLockReadGuard<'a, Shared<C>>),
+    // Joins a read scope that is already active on the current thread, see `Lock::join_read`.
+    Nested(&'a Shared<C>),
+}
+
+impl<'a, C> ReadGuard<'a, C>
+where
+    C: TypeConstructor,
+{
+    fn new(guard: RwLockReadGuard<'a, Shared<C>>, lock: &'a Lock<C>) -> Self {
+        let shared: *const Shared<C> = &*guard;
+
+        ReadGuard {
+            guard: ReadGuardInner::Locked(guard),
+            _release: Release::new(lock, ActiveScope::Read(shared as *const ())),
+        }
+    }
+
+    fn nested(shared: &'a Shared<C>, lock: &'a Lock<C>) -> Self {
+        let ptr: *const Shared<C> = shared;
+
+        ReadGuard {
+            guard: ReadGuardInner::Nested(shared),
+            _release: Release::new(lock, ActiveScope::Read(ptr as *const ())),
+        }
+    }
+}
+
+impl<C> Deref for ReadGuard<'_, C>
+where
+    C: TypeConstructor,
+{
+    type Target = Shared<C>;
+
+    fn deref(&self) -> &Self::Target {
+        match &self.guard {
+            ReadGuardInner::Locked(guard) => guard,
+            ReadGuardInner::Nested(shared) => shared,
+        }
+    }
+}
+
+struct WriteGuard<'a, C>
+where
+    C: TypeConstructor,
+{
+    guard: RwLockWriteGuard<'a, Shared<C>>,
+    _release: Release<'a, C>,
+}
+
+impl<'a, C> WriteGuard<'a, C>
+where
+    C: TypeConstructor,
+{
+    fn new(guard: RwLockWriteGuard<'a, Shared<C>>, lock: &'a Lock<C>) -> Self {
+        WriteGuard {
+            guard,
+            _release: Release::new(lock, ActiveScope::Write),
+        }
+    }
+}
+
+impl<C> Deref for WriteGuard<'_, C>
+where
+    C: TypeConstructor,
+{
+    type Target = Shared<C>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<C> DerefMut for WriteGuard<'_, C>
+where
+    C: TypeConstructor,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+/// Future that acquires a guard for a [Lock] without blocking the current thread.
+///
+/// Registers as a waiter before every attempt to acquire the guard, so that a release that happens
+/// after a failed attempt always wakes the task.
+struct Acquire<'a, C, G>
+where
+    C: TypeConstructor,
+{
+    lock: &'a Lock<C>,
+    try_acquire: fn(&'a Lock<C>) -> Option<G>,
+    waiter: Option<Listener<Mutex<Option<Waker>>>>,
+}
+
+impl<'a, C, G> Acquire<'a, C, G>
+where
+    C: TypeConstructor,
+{
+    fn new(lock: &'a Lock<C>, try_acquire: fn(&'a Lock<C>) -> Option<G>) -> Self {
+        Acquire {
+            lock,
+            try_acquire,
+            waiter: None,
+        }
+    }
+}
+
+impl<C, G> Acquire<'_, C, G>
+where
+    C: TypeConstructor,
+{
+    /// Parks the current thread until the guard is acquired, or until the `deadline` passes.
+    ///
+    /// A `deadline` of `None` never passes.
+    fn wait_until(mut self, deadline: Option<Instant>) -> Option<G> {
+        let waker = task::waker(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+
+        loop {
+            if let Poll::Ready(guard) = Pin::new(&mut self).poll(&mut cx) {
+                return Some(guard);
+            }
+
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+
+                    if now >= deadline {
+                        return None;
+                    }
+
+                    thread::park_timeout(deadline - now);
+                }
+                None => thread::park(),
+            }
+        }
+    }
+}
+
+/// Unparks a thread that waits for a [Lock], see [Acquire::wait_until].
+struct ThreadWaker(Thread);
+
+impl ArcWake for ThreadWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.0.unpark();
+    }
+}
+
+impl<C, G> Future for Acquire<'_, C, G>
+where
+    C: TypeConstructor,
+{
+    type Output = G;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match &self.waiter {
+            Some(waiter) => *waiter.lock().unwrap() = Some(cx.waker().clone()),
+            None => {
+                let waiter = self
+                    .lock
+                    .waiters
+                    .listener(Mutex::new(Some(cx.waker().clone())));
+
+                self.waiter = Some(waiter);
+            }
+        }
+
+        match (self.try_acquire)(self.lock) {
+            Some(guard) => Poll::Ready(guard),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<C, G> Unpin for Acquire<'_, C, G> where C: TypeConstructor {}
+
 /// Observable store that can contain [VersionCell]s.
 ///
 ///
@@ -73,9 +708,20 @@ where
 
         let data = unsafe { initializer(update_context_provider.update_context()) };
 
+        Store::from_parts(data, update_context_provider)
+    }
+
+    fn from_parts(
+        data: <C as TypeConstructor>::Type<'static>,
+        update_context_provider: UpdateContextProvider,
+    ) -> Self {
+        let version = AtomicU64::new(update_context_provider.next_version);
         let shared = Shared {
-            data,
+            data: Arc::new(data),
+            copy_data: OnceLock::new(),
             update_context_provider,
+            history: History::new(),
+            panic_policy: PanicPolicy::default(),
         };
 
         let store_id = STORE_ID_PROVIDER.inc();
@@ -84,6 +730,8 @@ where
             lock: Arc::new(Lock {
                 shared: RwLock::new(shared),
                 store_id,
+                version,
+                waiters: Broadcaster::new(),
             }),
             update_broadcaster: Arc::new(UpdateBroadcaster::new()),
         }
@@ -93,6 +741,15 @@ where
         self.lock.store_id
     }
 
+    /// Runs a read scope and returns the value returned by `f`.
+    ///
+    /// A read scope may be nested inside another read scope for the same store on the same thread,
+    /// in which case it joins the outer scope.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called inside an update scope for the same store on the same thread, as the read
+    /// scope would otherwise wait for the update scope to end and deadlock.
     pub fn with<F, O>(&self, f: F) -> O
     where
         F: for<'store> FnOnce(&<C as TypeConstructor>::Type<'store>, ReadContext<'store>) -> O,
@@ -100,85 +757,808 @@ where
         self.lock.with(f)
     }
 
-    pub fn update<F>(&self, f: F)
+    /// Runs a read scope like [Self::with] if it can be started without blocking the current
+    /// thread.
+    ///
+    /// Returns [StoreError::WouldBlock] if an update scope is running on another thread.
+    ///
+    /// "Nonblocking" refers to the store's lock only: when the read scope ends, the store briefly
+    /// locks the internal [Mutex] that holds the tasks waiting for the lock (see
+    /// [Self::with_async]) to wake them, which may wait for other threads doing the same.
+    pub fn with_nonblocking<F, O>(&self, f: F) -> Result<O, StoreError>
     where
-        F: for<'store> FnOnce(&<C as TypeConstructor>::Type<'store>, UpdateContext<'store>),
+        F: for<'store> FnOnce(&<C as TypeConstructor>::Type<'store>, ReadContext<'store>) -> O,
     {
-        let mut lock = self.lock.shared.write().expect("poisoned");
+        let lock = self.lock.try_read().ok_or(StoreError::WouldBlock)?;
 
-        let Shared {
-            data,
-            update_context_provider,
-        } = &mut *lock;
+        Ok(self.lock.read_scope(lock, f))
+    }
 
-        let result = unsafe {
-            f(
-                ::std::mem::transmute::<&mut <C as TypeConstructor>::Type<'static>, _>(data),
-                update_context_provider.update_context(),
-            );
+    /// Runs a read scope like [Self::with], but blocks the current thread for at most `timeout`
+    /// while waiting for an update scope on another thread to end.
+    ///
+    /// Returns [StoreError::TimedOut] if the timeout elapsed before the read scope could start. A
+    /// `timeout` too large to represent as a deadline blocks like [Self::with].
+    pub fn with_timeout<F, O>(&self, timeout: Duration, f: F) -> Result<O, StoreError>
+    where
+        F: for<'store> FnOnce(&<C as TypeConstructor>::Type<'store>, ReadContext<'store>) -> O,
+    {
+        let lock = self
+            .lock
+            .read_until(Instant::now().checked_add(timeout))
+            .ok_or(StoreError::TimedOut)?;
+
+        Ok(self.lock.read_scope(lock, f))
+    }
+
+    /// Returns an immutable view of the store's current data.
+    ///
+    /// Taking a snapshot is cheap: the snapshot shares the store's data until the next update
+    /// scope (or undo) on the store, which first replaces the store's data with a copy made with
+    /// [SnapshotClone]. This copies every [VersionedCell] in the data graph, along with its version
+    /// number, and takes time proportional to the size of the data graph; it happens at most once
+    /// per update scope, no matter how many snapshots share the data. The returned [Snapshot] can
+    /// be read with the same [ReadContext]-based API as the store, and memos associated with this
+    /// store can be refreshed against it. Reading a snapshot does not block update scopes on the
+    /// store, and may happen on another thread.
+    ///
+    /// The undo history (see [Self::set_history_depth]) moves to the copy along with the store's
+    /// data, as long as the data's [SnapshotClone::map_cells] finds every cell it refers to;
+    /// otherwise, the history is cleared.
+    ///
+    /// Note that copying moves the store's cells to new addresses, so a
+    /// [TrackedMemo](crate::memo::TrackedMemo) reports a change on its next refresh.
+    pub fn snapshot(&self) -> Snapshot<C>
+    where
+        for<'store> <C as TypeConstructor>::Type<'store>: SnapshotClone,
+    {
+        let lock = self.lock.read();
+
+        lock.copy_data.get_or_init(|| copy_data::<C>);
+
+        Snapshot::new(
+            lock.data.clone(),
+            self.lock.store_id,
+            lock.update_context_provider.next_version,
+        )
+    }
+
+    /// Returns the store's current version.
+    ///
+    /// The store version increases whenever a [VersionedCell] in the store is created, touched or
+    /// mutably borrowed, and never decreases. It can be used as a staleness token: take the
+    /// version when deriving data from the store, and later ask whether any cell changed since
+    /// with [VersionedCell::changed_since], or whether the store as a whole changed since by
+    /// comparing against a later version.
+    ///
+    /// Does not lock the store, so it may be called inside read and update scopes. Inside an
+    /// update scope, this returns the version the store had when the scope began, see
+    /// [UpdateContext::store_version] for the version including the scope's changes so far.
+    pub fn version(&self) -> u64 {
+        self.lock.version.load(Ordering::Acquire)
+    }
+
+    /// Whether any [VersionedCell] in the store was created, touched or mutably borrowed since the
+    /// store had the given `version`, see [Self::version].
+    pub fn changed_since(&self, version: u64) -> bool {
+        self.version() > version
+    }
+
+    /// Runs a read scope like [Self::with], but waits for the store's lock without blocking the
+    /// current thread.
+    ///
+    /// Use this instead of [Self::with] inside of async tasks: if an update scope is running on
+    /// another thread, [Self::with] blocks the executor thread until it ends, whereas the returned
+    /// future yields to the executor until the store's lock becomes available. Note that `f` itself
+    /// still runs synchronously once the lock is acquired.
+    ///
+    /// The lock is not fair: waiting tasks are not queued, but every task that waits for the lock
+    /// is woken whenever a scope ends, and then races other tasks and threads to acquire it. Under
+    /// heavy contention, in particular from threads that use the blocking methods, a task may
+    /// therefore have to wait for an arbitrarily long time (this especially affects
+    /// [Self::update_async], which needs exclusive access).
+    pub async fn with_async<F, O>(&self, f: F) -> O
+    where
+        F: for<'store> FnOnce(&<C as TypeConstructor>::Type<'store>, ReadContext<'store>) -> O,
+    {
+        let lock = self.lock.read_async().await;
+
+        self.lock.read_scope(lock, f)
+    }
+
+    /// Runs an update scope and returns the value returned by `f`.
+    ///
+    /// Update listeners (see [Self::on_update]) are notified when the scope ends, but only if a
+    /// [VersionedCell] was created, touched or mutably borrowed during the scope.
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics, the panic is propagated to the caller once the scope has ended, but the store
+    /// remains usable: later read and update scopes proceed as normal. By default, the changes that
+    /// were made before the panic are kept, and update listeners are notified of them before the
+    /// panic is propagated. See [Self::set_panic_policy] for rolling them back instead.
+    ///
+    /// Panics if called inside a read scope for the same store on the same thread, as the update
+    /// scope would otherwise wait for the read scope to end and deadlock.
+    ///
+    /// # Nested update scopes
+    ///
+    /// If called inside an update scope for the same store on the same thread (e.g. by a helper
+    /// function that is called from an update scope), `f` joins the outer scope: it receives the
+    /// outer scope's [UpdateContext], and update listeners are notified once the outer scope ends.
+    /// The same applies to [Self::update_with_status], [update_async](Self::update_async),
+    /// [Self::update_nonblocking] and [Self::update_timeout].
+    pub fn update<F, O>(&self, f: F) -> O
+    where
+        F: for<'store> FnOnce(&<C as TypeConstructor>::Type<'store>, UpdateContext<'store>) -> O,
+    {
+        self.update_with_status(f).value
+    }
+
+    /// Runs an update scope like [Self::update], but waits for the store's lock without blocking
+    /// the current thread.
+    ///
+    /// See [Self::with_async] for details. As with [Self::update_nonblocking], the
+    /// [Self::subscribe] callbacks are called on the thread that polls the future when the scope
+    /// ends.
+    pub async fn update_async<F, O>(&self, f: F) -> O
+    where
+        F: for<'store> FnOnce(&<C as TypeConstructor>::Type<'store>, UpdateContext<'store>) -> O,
+    {
+        let f = match self.lock.join_update(f) {
+            Ok(status) => return status.value,
+            Err(f) => f,
+        };
+
+        let lock = self.lock.write_async().await;
+
+        self.end_update(self.lock.update_scope(lock, false, f))
+            .value
+    }
+
+    /// Runs an update scope like [Self::update] if it can be started without blocking the
+    /// current thread.
+    ///
+    /// Returns [StoreError::WouldBlock] if another read or update scope is running. Note that this
+    /// is unrelated to [Self::try_update], which runs an update scope that may be rolled back.
+    ///
+    /// "Nonblocking" refers to the store's lock only. When the scope ends, the store briefly locks
+    /// internal [Mutex]es (the list of tasks waiting for the lock, the batch state of
+    /// [Self::begin_batch] and the buffers of the [Self::on_update] streams), which may wait for
+    /// other threads that are ending a scope at the same time. If the scope changed the store, the
+    /// [Self::subscribe] callbacks are also called on the current thread before this returns, and
+    /// block it for as long as they run.
+    pub fn update_nonblocking<F, O>(&self, f: F) -> Result<O, StoreError>
+    where
+        F: for<'store> FnOnce(&<C as TypeConstructor>::Type<'store>, UpdateContext<'store>) -> O,
+    {
+        let f = match self.lock.join_update(f) {
+            Ok(status) => return Ok(status.value),
+            Err(f) => f,
+        };
+
+        let lock = self.lock.try_write().ok_or(StoreError::WouldBlock)?;
+
+        Ok(self
+            .end_update(self.lock.update_scope(lock, false, f))
+            .value)
+    }
+
+    /// Runs an update scope like [Self::update], but blocks the current thread for at most
+    /// `timeout` while waiting for other read or update scopes to end.
+    ///
+    /// Returns [StoreError::TimedOut] if the timeout elapsed before the update scope could start. A
+    /// `timeout` too large to represent as a deadline blocks like [Self::update].
+    pub fn update_timeout<F, O>(&self, timeout: Duration, f: F) -> Result<O, StoreError>
+    where
+        F: for<'store> FnOnce(&<C as TypeConstructor>::Type<'store>, UpdateContext<'store>) -> O,
+    {
+        let f = match self.lock.join_update(f) {
+            Ok(status) => return Ok(status.value),
+            Err(f) => f,
         };
 
-        self.update_broadcaster.broadcast();
+        let lock = self
+            .lock
+            .write_until(Instant::now().checked_add(timeout))
+            .ok_or(StoreError::TimedOut)?;
+
+        Ok(self
+            .end_update(self.lock.update_scope(lock, false, f))
+            .value)
+    }
+
+    /// Runs an update scope and reports whether it changed the store.
+    ///
+    /// Behaves like [Self::update], but in addition to the value returned by `f`, the returned
+    /// [UpdateStatus] reports whether any [VersionedCell] was created, touched or mutably borrowed
+    /// during the scope.
+    pub fn update_with_status<F, O>(&self, f: F) -> UpdateStatus<O>
+    where
+        F: for<'store> FnOnce(&<C as TypeConstructor>::Type<'store>, UpdateContext<'store>) -> O,
+    {
+        match self.lock.join_update(f) {
+            Ok(status) => status,
+            Err(f) => self.end_update(self.lock.update(false, f)),
+        }
+    }
+
+    /// Notifies update listeners if the update scope that produced the `status` changed the store,
+    /// and resumes the scope's panic if it panicked.
+    fn end_update<O>(&self, status: UpdateStatus<thread::Result<O>>) -> UpdateStatus<O> {
+        if status.is_changed {
+            self.update_broadcaster.broadcast(status.version);
+        }
+
+        status.resume_unwind()
+    }
+
+    /// Runs an update scope that is rolled back if the scope returns an error.
+    ///
+    /// Behaves like [Self::update], except that `f` returns a [Result]. If `f` returns `Ok`, the
+    /// changes made in the scope are kept. If `f` returns `Err`, every [VersionedCell] that was
+    /// touched or mutably borrowed during the scope is restored to the value and version it had
+    /// when the scope began, and update listeners are not notified.
+    ///
+    /// As with [Iterator::try_fold], the `try_` prefix refers to `f` being fallible. Store methods
+    /// that do not wait for the store's lock are instead suffixed with `_nonblocking` (see
+    /// [Self::update_nonblocking]), and methods that wait for a limited time with `_timeout`.
+    ///
+    /// To be able to restore a [VersionedCell]'s value, the value is cloned when the cell is first
+    /// mutably borrowed inside the scope.
+    ///
+    /// If `f` panics, the scope is rolled back as if `f` returned `Err`, after which the panic is
+    /// propagated to the caller.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a [VersionedCell] whose value does not implement [Clone], or whose value owns
+    /// other [VersionedCell]s, is mutably borrowed inside the scope (except for the structure of a
+    /// collection whose elements can be cloned, see the [collections](crate::collections) module).
+    ///
+    /// Unlike [Self::update], this cannot join an outer update scope, as the outer scope's changes
+    /// could not be kept when this scope is rolled back; it panics if called inside a read or
+    /// update scope for the same store on the same thread.
+    ///
+    /// Requires the store's data to be [CellStable], so that the cells the scope modified cannot be
+    /// moved or dropped before they are restored.
+    pub fn try_update<F, R, E>(&self, f: F) -> Result<R, E>
+    where
+        F: for<'store> FnOnce(
+            &<C as TypeConstructor>::Type<'store>,
+            UpdateContext<'store>,
+        ) -> Result<R, E>,
+        for<'store> <C as TypeConstructor>::Type<'store>: CellStable,
+    {
+        let status = self.lock.update(true, |root, cx| {
+            let result = f(root, cx);
+
+            if result.is_err() {
+                // SAFETY: any borrows created by `f` have ended by the time it returns.
+                unsafe {
+                    cx.rollback();
+                }
+            }
+
+            result
+        });
+
+        if matches!(status.value, Ok(Ok(_))) && status.is_changed {
+            self.update_broadcaster.broadcast(status.version);
+        }
+
+        status.resume_unwind().value
+    }
+
+    /// Sets what happens to the changes of an update scope that panics, see [PanicPolicy].
+    ///
+    /// With [PanicPolicy::Rollback], every [VersionedCell] that was modified before the panic is
+    /// restored to the state it was in when the scope began, and update listeners are not
+    /// notified. To make this possible, the value of every [VersionedCell] that is mutably borrowed
+    /// inside an update scope is cloned when it is first borrowed (as for
+    /// [Self::set_history_depth]), which costs an allocation per modified cell in every update
+    /// scope. If the scope's changes cannot be reverted, because the scope mutably borrowed a
+    /// [VersionedCell] whose value does not implement [Clone] or owns other [VersionedCell]s, the
+    /// changes are kept as with [PanicPolicy::Keep].
+    ///
+    /// Requires the store's data to be [CellStable], so that the cells the scope modified cannot be
+    /// moved or dropped before they are restored.
+    pub fn set_panic_policy(&self, policy: PanicPolicy)
+    where
+        for<'store> <C as TypeConstructor>::Type<'store>: CellStable,
+    {
+        self.lock.write().panic_policy = policy;
+    }
+
+    /// Sets the maximum number of entries the store's undo history retains.
+    ///
+    /// The history is disabled by default (a depth of `0`). While the history is enabled, every
+    /// update scope that changes the store becomes an entry in the history that can be reverted
+    /// with [Self::undo] (see [Self::history_group] for combining several update scopes into a
+    /// single entry). If the history already holds more entries than `depth`, the oldest entries
+    /// are discarded; setting the depth to `0` disables the history and clears it.
+    ///
+    /// To be able to revert a [VersionedCell]'s value, the value is cloned when the cell is first
+    /// mutably borrowed inside an update scope. When an update scope mutably borrows a
+    /// [VersionedCell] whose value does not implement [Clone], or whose value owns other
+    /// [VersionedCell]s, it cannot be reverted and the entire history is cleared.
+    ///
+    /// Structural changes to a [VersionedVec](crate::collections::VersionedVec) or
+    /// [VersionedMap](crate::collections::VersionedMap) (adding, removing or reordering elements)
+    /// are the exception: these mutably borrow a cell that owns the element cells, but they can be
+    /// reverted as long as the elements (and keys) implement [Clone] and don't own other
+    /// [VersionedCell]s (see the [collections](crate::collections) module).
+    ///
+    /// Requires the store's data to be [CellStable], so that the cells recorded in the history
+    /// cannot be moved or dropped before they are reverted.
+    pub fn set_history_depth(&self, depth: usize)
+    where
+        for<'store> <C as TypeConstructor>::Type<'store>: CellStable,
+    {
+        self.lock.write().history.set_depth(depth);
+    }
+
+    /// Whether the store's history holds an entry that can be reverted with [Self::undo].
+    pub fn can_undo(&self) -> bool {
+        self.lock.read().history.can_undo()
+    }
+
+    /// Whether the store's history holds an entry that can be reapplied with [Self::redo].
+    pub fn can_redo(&self) -> bool {
+        self.lock.read().history.can_redo()
+    }
+
+    /// Removes all entries from the store's history.
+    pub fn clear_history(&self) {
+        self.lock.write().history.clear();
+    }
+
+    /// Reverts the changes made by the most recent entry in the store's history.
+    ///
+    /// Every [VersionedCell] that was modified by the entry's update scope(s) gets back the value
+    /// it had before, along with a new version number, so that the change can be observed like any
+    /// other update. Update listeners are notified. The entry can be reapplied with [Self::redo]
+    /// until the next update scope changes the store.
+    ///
+    /// Returns `false` if there was no entry to undo.
+    pub fn undo(&self) -> bool {
+        if let Some(version) = self.lock.undo(false) {
+            self.update_broadcaster.broadcast(version);
+
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Reapplies the changes of the entry that was most recently reverted with [Self::undo].
+    ///
+    /// Returns `false` if there was no entry to redo.
+    pub fn redo(&self) -> bool {
+        if let Some(version) = self.lock.undo(true) {
+            self.update_broadcaster.broadcast(version);
+
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Combines all update scopes that change the store while `f` runs into a single history
+    /// entry.
+    ///
+    /// Groups may be nested, in which case all update scopes are combined into the outermost
+    /// group's entry. Note that the group is store-wide: update scopes that run on other threads
+    /// while `f` runs are also combined into the group's entry.
+    pub fn history_group<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        struct GroupGuard<'a, C>
+        where
+            C: TypeConstructor,
+        {
+            lock: &'a Lock<C>,
+        }
+
+        impl<C> Drop for GroupGuard<'_, C>
+        where
+            C: TypeConstructor,
+        {
+            fn drop(&mut self) {
+                if let Ok(lock) = self.lock.shared.write() {
+                    WriteGuard::new(lock, self.lock).history.end_group();
+                }
+            }
+        }
+
+        self.lock.write().history.begin_group();
+
+        let _guard = GroupGuard { lock: &self.lock };
+
+        f()
+    }
+
+    /// Registers a `callback` that is called whenever an update scope for this store ends, if that
+    /// update scope changed the store.
+    ///
+    /// The callback is called synchronously on the thread that ran the update scope, after the
+    /// store's write lock was released, so it may read or update the store, subscribe further
+    /// callbacks, or drop subscriptions. An update scope that runs inside the callback notifies
+    /// the callbacks again once it ends, so a callback that unconditionally updates the store
+    /// recurses without end.
+    ///
+    /// The callback remains registered until the returned [Subscription] is dropped. Callbacks are
+    /// collected before any of them is called, so a callback whose [Subscription] is dropped while
+    /// the callbacks are being called (by another callback or on another thread) may still be
+    /// called one more time.
+    pub fn subscribe<F>(&self, callback: F) -> Subscription
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        Subscription {
+            _listener: self.update_broadcaster.subscriber(Arc::new(callback)),
+        }
+    }
+
+    /// Defers update notifications until `f` returns, see [Self::begin_batch].
+    pub fn batch<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        let _batch = self.begin_batch();
+
+        f()
+    }
+
+    /// Begins a batch that defers update notifications until the returned [UpdateBatch] is
+    /// dropped.
+    ///
+    /// Update scopes that change the store during the batch do not notify update listeners (see
+    /// [Self::on_update] and [Self::subscribe]) when they end. Instead, when the batch ends,
+    /// listeners are notified once for all of the batch's updates, with a single [UpdateEvent] that
+    /// covers all of them. This avoids observing the intermediate states of an operation that is
+    /// made up of several update scopes.
+    ///
+    /// Batches may be nested, in which case listeners are notified when the outermost batch ends.
+    /// Note that the batch is store-wide: notifications for update scopes that run on other
+    /// threads during the batch are also deferred.
+    pub fn begin_batch(&self) -> UpdateBatch {
+        self.update_broadcaster.begin_batch();
 
-        result
+        UpdateBatch {
+            broadcaster: self.update_broadcaster.clone(),
+        }
     }
 
     /// Returns a stream that, once spawned, will be notified whenever an update scope for this
-    /// store ends.
+    /// store ends, if that update scope changed the store.
+    ///
+    /// If the store updates more than once before the stream is polled, the updates are coalesced
+    /// into a single [UpdateEvent]. See [Self::on_update_buffered] for a stream that reports every
+    /// update separately.
     pub fn on_update(&self) -> OnUpdate {
         OnUpdate {
             broadcaster: Arc::downgrade(&self.update_broadcaster),
+            capacity: None,
+            listener: None,
+        }
+    }
+
+    /// Returns a stream like [Self::on_update], but that buffers up to `capacity` events, so that
+    /// every update is reported as a separate [UpdateEvent].
+    ///
+    /// If the buffer is full when the store updates, the update is coalesced into the most recent
+    /// buffered event; a consumer can detect this by an event's [count](UpdateEvent::count) being
+    /// greater than `1`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is `0`.
+    pub fn on_update_buffered(&self, capacity: usize) -> OnUpdate {
+        assert!(capacity > 0, "capacity must be greater than `0`");
+
+        OnUpdate {
+            broadcaster: Arc::downgrade(&self.update_broadcaster),
+            capacity: Some(capacity),
             listener: None,
         }
     }
 }
 
-impl<C> Clone for Store<C>
-where
-    C: TypeConstructor,
-{
-    fn clone(&self) -> Self {
-        Store {
-            lock: self.lock.clone(),
-            update_broadcaster: self.update_broadcaster.clone(),
+#[cfg(feature = "serde")]
+impl<C> serde::Serialize for Store<C>
+where
+    C: TypeConstructor,
+    for<'store> <C as TypeConstructor>::Type<'store>: serde::Serialize,
+{
+    /// Serializes the store's root inside a read scope.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.lock.read().data.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, C> serde::Deserialize<'de> for Store<C>
+where
+    C: TypeConstructor,
+    for<'store> <C as TypeConstructor>::Type<'store>: serde::Deserialize<'de>,
+{
+    /// Deserializes a new store's root inside an update scope.
+    ///
+    /// Every [VersionedCell] in the data graph is created with a new version number, as if by
+    /// [VersionedCell::new].
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let mut update_context_provider = UpdateContextProvider::new();
+
+        let data = {
+            let _guard = DeserializationScope::enter(&mut update_context_provider);
+
+            <C as TypeConstructor>::Type::<'static>::deserialize(deserializer)?
+        };
+
+        Ok(Store::from_parts(data, update_context_provider))
+    }
+}
+
+#[cfg(feature = "serde")]
+thread_local! {
+    static DESERIALIZATION_CONTEXT: Cell<Option<*mut UpdateContextProvider>> =
+        const { Cell::new(None) };
+}
+
+/// Makes an update context available to [VersionedCell]s that are deserialized on the current
+/// thread for as long as the scope is alive.
+#[cfg(feature = "serde")]
+struct DeserializationScope {
+    previous: Option<*mut UpdateContextProvider>,
+}
+
+#[cfg(feature = "serde")]
+impl DeserializationScope {
+    fn enter(provider: &mut UpdateContextProvider) -> Self {
+        let previous = DESERIALIZATION_CONTEXT.with(|context| context.replace(Some(provider)));
+
+        DeserializationScope { previous }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Drop for DeserializationScope {
+    fn drop(&mut self) {
+        DESERIALIZATION_CONTEXT.with(|context| context.set(self.previous));
+    }
+}
+
+impl<C> Clone for Store<C>
+where
+    C: TypeConstructor,
+{
+    fn clone(&self) -> Self {
+        Store {
+            lock: self.lock.clone(),
+            update_broadcaster: self.update_broadcaster.clone(),
+        }
+    }
+}
+
+/// The outcome of an update scope, see [Store::update_with_status].
+pub struct UpdateStatus<T> {
+    /// The value returned by the update scope.
+    pub value: T,
+
+    /// Whether any [VersionedCell] was created, touched or mutably borrowed during the update
+    /// scope.
+    pub is_changed: bool,
+
+    /// The store's version when the update scope ended, see [Store::version].
+    pub version: u64,
+}
+
+impl<T> UpdateStatus<thread::Result<T>> {
+    /// Unwraps the value of an update scope, or resumes the panic that ended the scope.
+    fn resume_unwind(self) -> UpdateStatus<T> {
+        match self.value {
+            Ok(value) => UpdateStatus {
+                value,
+                is_changed: self.is_changed,
+                version: self.version,
+            },
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+/// What happens to the changes of an update scope that panics, see [Store::set_panic_policy].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PanicPolicy {
+    /// Keeps the changes that were made before the panic, and notifies update listeners of them.
+    #[default]
+    Keep,
+    /// Restores every [VersionedCell] that was modified before the panic, if possible.
+    Rollback,
+}
+
+/// Error returned when a store cannot be accessed without blocking, see e.g.
+/// [Store::with_nonblocking].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StoreError {
+    /// The store is locked by another scope, and the access would have blocked.
+    WouldBlock,
+    /// The store remained locked by other scopes until the timeout elapsed.
+    TimedOut,
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::WouldBlock => fmt::Display::fmt("store access would block", f),
+            StoreError::TimedOut => fmt::Display::fmt("store access timed out", f),
+        }
+    }
+}
+
+impl error::Error for StoreError {}
+
+/// One or more store updates, as reported by [OnUpdate].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct UpdateEvent {
+    /// The store's version after the first update covered by this event.
+    pub first_version: u64,
+
+    /// The store's version after the last update covered by this event.
+    pub last_version: u64,
+
+    /// The number of updates covered by this event.
+    pub count: usize,
+}
+
+impl UpdateEvent {
+    fn new(version: u64) -> Self {
+        UpdateEvent {
+            first_version: version,
+            last_version: version,
+            count: 1,
         }
     }
+
+    /// Extends this event to also cover the updates covered by the `later` event.
+    fn merge(&mut self, later: UpdateEvent) {
+        self.last_version = later.last_version;
+        self.count += later.count;
+    }
 }
 
 struct Waiter {
     terminated: bool,
     waker: Option<Waker>,
+    events: VecDeque<UpdateEvent>,
+    // `None` if events are coalesced.
+    capacity: Option<usize>,
+}
+
+impl Waiter {
+    fn push(&mut self, event: UpdateEvent) {
+        let is_full = self.events.len() >= self.capacity.unwrap_or(1);
+
+        match self.events.back_mut() {
+            Some(last) if is_full => last.merge(event),
+            _ => self.events.push_back(event),
+        }
+    }
 }
 
 type UpdateListener = Listener<Mutex<Waiter>>;
 
+type Callback = Arc<dyn Fn() + Send + Sync>;
+
 struct UpdateBroadcaster {
     inner: Broadcaster<Mutex<Waiter>>,
+    subscribers: Broadcaster<Callback>,
+    batch: Mutex<BatchState>,
+}
+
+/// Tracks the batches that are active for a store, see [Store::batch].
+struct BatchState {
+    depth: usize,
+    // The updates that occurred during the outermost active batch, if any.
+    pending: Option<UpdateEvent>,
 }
 
 impl UpdateBroadcaster {
     fn new() -> Self {
         UpdateBroadcaster {
             inner: Broadcaster::new(),
+            subscribers: Broadcaster::new(),
+            batch: Mutex::new(BatchState {
+                depth: 0,
+                pending: None,
+            }),
         }
     }
 
-    fn broadcast(&self) {
-        self.inner.broadcast(|waiter| {
-            let mut waiter = waiter.lock().unwrap();
+    fn broadcast(&self, version: u64) {
+        self.broadcast_event(UpdateEvent::new(version));
+    }
 
-            if let Some(waker) = waiter.waker.take() {
-                waker.wake();
+    fn broadcast_event(&self, event: UpdateEvent) {
+        {
+            let mut batch = self.batch.lock().unwrap();
+
+            if batch.depth > 0 {
+                match &mut batch.pending {
+                    Some(pending) => pending.merge(event),
+                    pending => *pending = Some(event),
+                }
+
+                return;
             }
-        })
+
+            // Keep holding the batch lock while notifying the streams, so that a batch that ends
+            // concurrently can't deliver its (earlier) event after this one.
+            self.inner.broadcast(|waiter| {
+                let mut waiter = waiter.lock().unwrap();
+
+                waiter.push(event);
+
+                if let Some(waker) = waiter.waker.take() {
+                    waker.wake();
+                }
+            });
+        }
+
+        // Call the callbacks after releasing the subscriber list's lock, so that they may
+        // subscribe, drop subscriptions or update the store.
+        let mut callbacks = Vec::new();
+
+        self.subscribers
+            .broadcast(|callback| callbacks.push(callback.clone()));
+
+        for callback in callbacks {
+            callback();
+        }
+    }
+
+    fn begin_batch(&self) {
+        self.batch.lock().unwrap().depth += 1;
+    }
+
+    fn end_batch(&self) {
+        let pending = {
+            let mut batch = self.batch.lock().unwrap();
+
+            batch.depth -= 1;
+
+            if batch.depth == 0 {
+                batch.pending.take()
+            } else {
+                None
+            }
+        };
+
+        if let Some(event) = pending {
+            self.broadcast_event(event);
+        }
     }
 
-    fn listener(&self, cx: &mut Context<'_>) -> UpdateListener {
+    fn listener(&self, cx: &mut Context<'_>, capacity: Option<usize>) -> UpdateListener {
         self.inner.listener(Mutex::new(Waiter {
             terminated: false,
             waker: Some(cx.waker().clone()),
+            events: VecDeque::new(),
+            capacity,
         }))
     }
+
+    fn subscriber(&self, callback: Callback) -> Listener<Callback> {
+        self.subscribers.listener(callback)
+    }
 }
 
 impl Drop for UpdateBroadcaster {
@@ -195,20 +1575,40 @@ impl Drop for UpdateBroadcaster {
     }
 }
 
+/// Defers a store's update notifications for as long as it is alive, see [Store::begin_batch].
+pub struct UpdateBatch {
+    broadcaster: Arc<UpdateBroadcaster>,
+}
+
+impl Drop for UpdateBatch {
+    fn drop(&mut self) {
+        self.broadcaster.end_batch();
+    }
+}
+
+/// Keeps a callback registered with [Store::subscribe] for as long as it is alive.
+///
+/// The callback is unregistered when the [Subscription] is dropped.
+pub struct Subscription {
+    _listener: Listener<Callback>,
+}
+
+/// A stream of the updates to a store, see [Store::on_update] and [Store::on_update_buffered].
 pub struct OnUpdate {
     broadcaster: Weak<UpdateBroadcaster>,
+    capacity: Option<usize>,
     listener: Option<Listener<Mutex<Waiter>>>,
 }
 
 impl Stream for OnUpdate {
-    type Item = ();
+    type Item = UpdateEvent;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         match &mut self.listener {
             None => {
                 // Initialize if the broad caster is still alive, or terminate immediately
                 if let Some(broadcaster) = self.broadcaster.upgrade() {
-                    self.listener = Some(broadcaster.listener(cx));
+                    self.listener = Some(broadcaster.listener(cx, self.capacity));
 
                     Poll::Pending
                 } else {
@@ -218,12 +1618,12 @@ impl Stream for OnUpdate {
             Some(listener) => {
                 let mut waiter = listener.lock().unwrap();
 
-                if waiter.terminated {
-                    Poll::Ready(None)
-                } else if waiter.waker.is_none() {
-                    waiter.waker = Some(cx.waker().clone());
+                waiter.waker = Some(cx.waker().clone());
 
-                    Poll::Ready(Some(()))
+                if let Some(event) = waiter.events.pop_front() {
+                    Poll::Ready(Some(event))
+                } else if waiter.terminated {
+                    Poll::Ready(None)
                 } else {
                     Poll::Pending
                 }
@@ -236,6 +1636,7 @@ impl Clone for OnUpdate {
     fn clone(&self) -> Self {
         OnUpdate {
             broadcaster: self.broadcaster.clone(),
+            capacity: self.capacity,
             listener: None,
         }
     }
@@ -244,13 +1645,20 @@ impl Clone for OnUpdate {
 #[derive(Clone, Copy)]
 pub struct ReadContext<'store> {
     store_id: usize,
+    version: u64,
+    // Opting to use a raw pointer here rather than a reference, as the tracker does not live for
+    // `'store`; a tracking context only exists inside of a tracked memo's selector, see
+    // `ReadContext::tracking`.
+    tracker: Option<NonNull<Tracker>>,
     _scope_marker: marker::PhantomData<Cell<&'store ()>>,
 }
 
 impl<'store> ReadContext<'store> {
-    unsafe fn new(store_id: usize) -> ReadContext<'store> {
+    pub(crate) unsafe fn new(store_id: usize, version: u64) -> ReadContext<'store> {
         ReadContext {
             store_id,
+            version,
+            tracker: None,
             _scope_marker: marker::PhantomData,
         }
     }
@@ -258,46 +1666,677 @@ impl<'store> ReadContext<'store> {
     pub fn store_id(&self) -> usize {
         self.store_id
     }
+
+    /// Returns the version the store had when this read scope began, see [Store::version].
+    ///
+    /// The version cannot change for the duration of the read scope.
+    pub fn store_version(&self) -> u64 {
+        self.version
+    }
+
+    /// Returns a context that records every cell that is dereferenced with it into the `tracker`.
+    ///
+    /// # Safety
+    ///
+    /// The returned context must not be used after the `tracker` is dropped.
+    pub(crate) unsafe fn tracking(&self, tracker: &Tracker) -> ReadContext<'store> {
+        ReadContext {
+            store_id: self.store_id,
+            version: self.version,
+            tracker: Some(NonNull::from(tracker)),
+            _scope_marker: marker::PhantomData,
+        }
+    }
+
+    /// Returns the tracker that this context records reads into, if it is a tracking context.
+    pub(crate) fn tracker(&self) -> Option<&Tracker> {
+        // SAFETY: see `tracking`.
+        self.tracker.map(|tracker| unsafe { tracker.as_ref() })
+    }
+
+    pub(crate) fn track<T: 'store>(&self, cell: &VersionedCell<'store, T>) {
+        if let Some(tracker) = self.tracker() {
+            tracker.record(cell);
+        }
+    }
 }
 
+// SAFETY: the tracker is `Sync`.
+unsafe impl Send for ReadContext<'_> {}
+
 #[derive(Clone, Copy)]
 pub struct UpdateContext<'store> {
     // Opting to use a raw pointer here rather than a reference or cell, so the context can by Copy.
-    next_version: *mut u64,
+    provider: *mut UpdateContextProvider,
     _scope_marker: marker::PhantomData<Cell<&'store ()>>,
 }
 
-impl UpdateContext<'_> {
+impl<'store> UpdateContext<'store> {
+    /// Returns a context for the given `provider`.
+    ///
+    /// # Safety
+    ///
+    /// The `provider` must belong to an update scope that is running on the current thread.
+    unsafe fn from_provider(provider: *mut UpdateContextProvider) -> Self {
+        UpdateContext {
+            provider,
+            _scope_marker: marker::PhantomData,
+        }
+    }
+
+    /// Returns the version the store will have if the update scope ends without making further
+    /// changes, see [Store::version].
+    pub fn store_version(&self) -> u64 {
+        // SAFETY: see `next_version`.
+        unsafe { (*self.provider).next_version }
+    }
+
     pub(crate) fn next_version(&self) -> u64 {
         // SAFETY: there is only ever a single update scope, and though there can be many
         // `UpdateContext`s within that scope (it implements `Copy`), `next_version` can never be
         // called concurrently
         unsafe {
-            let next_version = *self.next_version;
+            let next_version = (*self.provider).next_version;
 
-            *self.next_version = next_version + 1;
+            (*self.provider).next_version = next_version + 1;
 
             next_version
         }
     }
+
+    /// Returns the update context of the store that is currently being deserialized on this
+    /// thread, if any.
+    ///
+    /// # Safety
+    ///
+    /// The returned context may only be used to create cells that become part of the data graph of
+    /// the store that is being deserialized.
+    #[cfg(feature = "serde")]
+    pub(crate) unsafe fn deserialization_context() -> Option<UpdateContext<'store>> {
+        DESERIALIZATION_CONTEXT.with(|context| {
+            context.get().map(|provider| UpdateContext {
+                provider,
+                _scope_marker: marker::PhantomData,
+            })
+        })
+    }
+
+    pub(crate) fn record_value<T: 'store>(&self, cell: &VersionedCell<'store, T>) {
+        // SAFETY: see `next_version`.
+        unsafe {
+            if let Some(journal) = &mut (*self.provider).journal {
+                journal.record_value(cell);
+            }
+        }
+    }
+
+    pub(crate) fn record_structure<T: Structure + 'store>(&self, cell: &VersionedCell<'store, T>) {
+        // SAFETY: see `next_version`.
+        unsafe {
+            if let Some(journal) = &mut (*self.provider).journal {
+                journal.record_structure(cell);
+            }
+        }
+    }
+
+    pub(crate) fn record_version<T: 'store>(&self, cell: &VersionedCell<'store, T>) {
+        // SAFETY: see `next_version`.
+        unsafe {
+            if let Some(journal) = &mut (*self.provider).journal {
+                journal.record_version(cell);
+            }
+        }
+    }
+
+    /// Restores all cells recorded in the scope's journal to the state they were in when the scope
+    /// began, and starts a new journal.
+    ///
+    /// # Safety
+    ///
+    /// None of the cells in the store may be borrowed.
+    unsafe fn rollback(&self) {
+        if let Some(journal) = &mut (*self.provider).journal {
+            journal.take().rollback();
+        }
+    }
 }
 
 #[doc(hidden)]
 struct UpdateContextProvider {
     next_version: u64,
+    journal: Option<Journal>,
 }
 
 impl UpdateContextProvider {
     #[doc(hidden)]
     fn new() -> Self {
-        UpdateContextProvider { next_version: 0 }
+        UpdateContextProvider {
+            next_version: 0,
+            journal: None,
+        }
     }
 
     #[doc(hidden)]
     unsafe fn update_context<'store>(&mut self) -> UpdateContext<'store> {
         UpdateContext {
-            next_version: &mut self.next_version as *mut u64,
+            provider: self as *mut UpdateContextProvider,
             _scope_marker: marker::PhantomData,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+
+    use futures::executor::block_on;
+    use futures::task::noop_waker_ref;
+    use futures::Stream;
+
+    use super::{OnUpdate, PanicPolicy, Store, Subscription, UpdateEvent};
+    use crate::collections::VersionedVec;
+    use crate::versioned_cell::VersionedCell;
+
+    struct Root<'store> {
+        a: VersionedCell<'store, u32>,
+        b: VersionedVec<'store, u32>,
+        c: VersionedCell<'store, Vec<VersionedCell<'store, u32>>>,
+    }
+
+    crate::gen_type_constructor!(Root, RootTC);
+
+    fn store() -> Store<RootTC> {
+        Store::initialize(|cx| Root {
+            a: VersionedCell::new(cx, 1),
+            b: VersionedVec::new(cx),
+            c: VersionedCell::new(cx, Vec::new()),
+        })
+    }
+
+    fn count_updates(store: &Store<RootTC>) -> (Arc<AtomicUsize>, Subscription) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let subscription = store.subscribe({
+            let count = count.clone();
+
+            move || {
+                count.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        (count, subscription)
+    }
+
+    /// Returns the next event from the `stream` if one is ready.
+    fn next_event(stream: &mut OnUpdate) -> Option<UpdateEvent> {
+        let mut cx = Context::from_waker(noop_waker_ref());
+
+        match Pin::new(stream).poll_next(&mut cx) {
+            Poll::Ready(event) => event,
+            Poll::Pending => None,
+        }
+    }
+
+    #[test]
+    fn buffered_stream_reports_every_update() {
+        let store = store();
+        let mut stream = store.on_update_buffered(3);
+
+        // The stream starts listening when it is first polled.
+        assert_eq!(next_event(&mut stream), None);
+
+        let versions: Vec<_> = (2..5)
+            .map(|value| {
+                store.update(|root, cx| *root.a.borrow_mut(cx) = value);
+
+                store.version()
+            })
+            .collect();
+
+        for version in versions {
+            assert_eq!(
+                next_event(&mut stream),
+                Some(UpdateEvent {
+                    first_version: version,
+                    last_version: version,
+                    count: 1,
+                })
+            );
+        }
+
+        assert_eq!(next_event(&mut stream), None);
+    }
+
+    #[test]
+    fn buffered_stream_coalesces_overflow() {
+        let store = store();
+        let mut stream = store.on_update_buffered(2);
+
+        assert_eq!(next_event(&mut stream), None);
+
+        let versions: Vec<_> = (2..6)
+            .map(|value| {
+                store.update(|root, cx| *root.a.borrow_mut(cx) = value);
+
+                store.version()
+            })
+            .collect();
+
+        assert_eq!(
+            next_event(&mut stream),
+            Some(UpdateEvent {
+                first_version: versions[0],
+                last_version: versions[0],
+                count: 1,
+            })
+        );
+        assert_eq!(
+            next_event(&mut stream),
+            Some(UpdateEvent {
+                first_version: versions[1],
+                last_version: versions[3],
+                count: 3,
+            })
+        );
+        assert_eq!(next_event(&mut stream), None);
+    }
+
+    #[test]
+    fn unbuffered_stream_coalesces_updates() {
+        let store = store();
+        let mut stream = store.on_update();
+
+        assert_eq!(next_event(&mut stream), None);
+
+        let first_version = store.update(|root, cx| {
+            *root.a.borrow_mut(cx) = 2;
+
+            cx.store_version()
+        });
+
+        store.update(|root, cx| *root.a.borrow_mut(cx) = 3);
+
+        assert_eq!(
+            next_event(&mut stream),
+            Some(UpdateEvent {
+                first_version,
+                last_version: store.version(),
+                count: 2,
+            })
+        );
+        assert_eq!(next_event(&mut stream), None);
+    }
+
+    #[test]
+    fn nested_batches_notify_subscribers_once() {
+        let store = store();
+        let (count, _subscription) = count_updates(&store);
+
+        store.batch(|| {
+            store.update(|root, cx| *root.a.borrow_mut(cx) = 2);
+
+            store.batch(|| {
+                store.update(|root, cx| *root.a.borrow_mut(cx) = 3);
+                store.update(|root, cx| root.b.push(cx, 1));
+            });
+
+            // Ending the inner batch does not notify.
+            assert_eq!(count.load(Ordering::SeqCst), 0);
+
+            store.update(|root, cx| *root.a.borrow_mut(cx) = 4);
+        });
+
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn nested_batches_produce_one_merged_event() {
+        let store = store();
+        let mut stream = store.on_update();
+        let mut buffered = store.on_update_buffered(10);
+
+        assert_eq!(next_event(&mut stream), None);
+        assert_eq!(next_event(&mut buffered), None);
+
+        let (first_version, last_version) = store.batch(|| {
+            let first_version = store.update(|root, cx| {
+                *root.a.borrow_mut(cx) = 2;
+
+                cx.store_version()
+            });
+
+            store.batch(|| {
+                store.update(|root, cx| *root.a.borrow_mut(cx) = 3);
+                store.update(|root, cx| root.b.push(cx, 1));
+            });
+
+            assert_eq!(next_event(&mut stream), None);
+            assert_eq!(next_event(&mut buffered), None);
+
+            store.update(|root, cx| *root.a.borrow_mut(cx) = 4);
+
+            (first_version, store.version())
+        });
+
+        let event = UpdateEvent {
+            first_version,
+            last_version,
+            count: 4,
+        };
+
+        // Even a buffered stream receives a single event for the batch.
+        for stream in [&mut stream, &mut buffered] {
+            assert_eq!(next_event(stream), Some(event));
+            assert_eq!(next_event(stream), None);
+        }
+    }
+
+    #[test]
+    fn panic_keeps_changes_by_default() {
+        let store = store();
+        let (count, _subscription) = count_updates(&store);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            store.update(|root, cx| {
+                *root.a.borrow_mut(cx) = 2;
+
+                panic!("update failed");
+            })
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(store.with(|root, cx| *root.a.deref(cx)), 2);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn panic_rolls_back_without_history() {
+        let store = store();
+        let (count, _subscription) = count_updates(&store);
+
+        store.set_panic_policy(PanicPolicy::Rollback);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            store.update(|root, cx| {
+                *root.a.borrow_mut(cx) = 2;
+
+                panic!("update failed");
+            })
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(store.with(|root, cx| *root.a.deref(cx)), 1);
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn panic_after_unrevertable_change_notifies() {
+        let store = store();
+        let (count, _subscription) = count_updates(&store);
+
+        store.set_panic_policy(PanicPolicy::Rollback);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            store.update(|root, cx| {
+                let cell = VersionedCell::new(cx, 1);
+
+                root.c.borrow_mut(cx).push(cell);
+
+                panic!("update failed");
+            })
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(store.with(|root, cx| root.c.deref(cx).len()), 1);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn timeout_overflow_does_not_panic() {
+        let store = store();
+
+        assert_eq!(
+            store.with_timeout(Duration::MAX, |root, cx| *root.a.deref(cx)),
+            Ok(1)
+        );
+        assert_eq!(
+            store.update_timeout(Duration::MAX, |root, cx| *root.a.borrow(cx)),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn update_async_joins_outer_scope() {
+        let store = store();
+        let (count, _subscription) = count_updates(&store);
+
+        store.update(|root, cx| {
+            *root.a.borrow_mut(cx) = 2;
+
+            block_on(store.update_async(|root, cx| *root.a.borrow_mut(cx) += 1));
+        });
+
+        assert_eq!(store.with(|root, cx| *root.a.deref(cx)), 3);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    /// Returns the message of a panic caught by `panic::catch_unwind`.
+    fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+        match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => payload.downcast_ref::<&str>().unwrap().to_string(),
+        }
+    }
+
+    #[test]
+    fn read_joins_outer_read() {
+        let store = store();
+
+        let (outer, inner, nonblocking) = store.with(|root, cx| {
+            (
+                *root.a.deref(cx),
+                store.with(|root, cx| *root.a.deref(cx)),
+                store.with_nonblocking(|root, cx| *root.a.deref(cx)),
+            )
+        });
+
+        assert_eq!(outer, 1);
+        assert_eq!(inner, 1);
+        assert_eq!(nonblocking, Ok(1));
+    }
+
+    #[test]
+    fn update_joins_outer_update() {
+        let store = store();
+        let (count, _subscription) = count_updates(&store);
+
+        let status = store.update(|root, cx| {
+            *root.a.borrow_mut(cx) = 2;
+
+            let status = store.update_with_status(|root, cx| {
+                let value = *root.a.borrow(cx);
+
+                *root.a.borrow_mut(cx) = value + 1;
+
+                value
+            });
+
+            assert_eq!(count.load(Ordering::SeqCst), 0);
+            assert_eq!(
+                store.update_nonblocking(|root, cx| *root.a.borrow(cx)),
+                Ok(3)
+            );
+
+            status
+        });
+
+        assert_eq!(status.value, 2);
+        assert!(status.is_changed);
+        assert_eq!(store.with(|root, cx| *root.a.deref(cx)), 3);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn update_inside_read_panics() {
+        let store = store();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            store.with(|_, _| store.update(|root, cx| *root.a.borrow_mut(cx) = 2))
+        }));
+
+        let message = panic_message(result.unwrap_err());
+
+        assert!(message.contains("inside a read scope"), "{}", message);
+        assert!(
+            message.contains(&format!("store {} ", store.id())),
+            "{}",
+            message
+        );
+
+        // The store remains usable.
+        store.update(|root, cx| *root.a.borrow_mut(cx) = 3);
+        assert_eq!(store.with(|root, cx| *root.a.deref(cx)), 3);
+    }
+
+    #[test]
+    fn read_inside_update_panics() {
+        let store = store();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            store.update(|_, _| store.with(|root, cx| *root.a.deref(cx)))
+        }));
+
+        let message = panic_message(result.unwrap_err());
+
+        assert!(message.contains("inside an update scope"), "{}", message);
+        assert!(
+            message.contains(&format!("store {} ", store.id())),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn try_update_inside_update_panics() {
+        let store = store();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            store.update(|_, _| store.try_update(|_, _| Ok::<_, ()>(())))
+        }));
+
+        let message = panic_message(result.unwrap_err());
+
+        assert!(
+            message.contains(&format!("store {} ", store.id())),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn version_inside_update_scope() {
+        let store = store();
+        let version = store.version();
+
+        let (outer, inner) = store.update(|root, cx| {
+            *root.a.borrow_mut(cx) = 2;
+
+            (store.version(), cx.store_version())
+        });
+
+        assert_eq!(outer, version);
+        assert!(inner > version);
+        assert_eq!(store.version(), inner);
+    }
+
+    #[test]
+    fn callback_can_update_and_subscribe() {
+        let store = store();
+        let subscriptions = Arc::new(Mutex::new(Vec::new()));
+
+        let _subscription = store.subscribe({
+            let store = store.clone();
+            let subscriptions = subscriptions.clone();
+
+            move || {
+                if store.with(|root, cx| *root.a.deref(cx)) == 2 {
+                    store.update(|root, cx| *root.a.borrow_mut(cx) = 3);
+
+                    let subscription = store.subscribe(|| {});
+
+                    subscriptions.lock().unwrap().push(subscription);
+                }
+
+                subscriptions.lock().unwrap().clear();
+            }
+        });
+
+        store.update(|root, cx| *root.a.borrow_mut(cx) = 2);
+
+        assert_eq!(store.with(|root, cx| *root.a.deref(cx)), 3);
+        assert!(subscriptions.lock().unwrap().is_empty());
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod serde_tests {
+    use super::Store;
+    use crate::collections::VersionedVec;
+    use crate::versioned_cell::VersionedCell;
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct Root<'store> {
+        a: VersionedCell<'store, String>,
+        b: VersionedVec<'store, u32>,
+    }
+
+    crate::gen_type_constructor!(Root, RootTC);
+
+    #[test]
+    fn round_trip() {
+        let store = Store::<RootTC>::initialize(|cx| Root {
+            a: VersionedCell::new(cx, String::from("a")),
+            b: VersionedVec::from_vec(cx, vec![1, 2]),
+        });
+
+        let json = serde_json::to_string(&store).unwrap();
+
+        assert_eq!(json, r#"{"a":"a","b":[1,2]}"#);
+
+        let copy: Store<RootTC> = serde_json::from_str(&json).unwrap();
+
+        copy.with(|root, cx| {
+            assert_eq!(root.a.deref(cx), "a");
+            assert_eq!(
+                root.b
+                    .deref(cx)
+                    .iter()
+                    .map(|cell| *cell.deref(cx))
+                    .collect::<Vec<_>>(),
+                vec![1, 2]
+            );
+        });
+
+        copy.update(|root, cx| root.b.push(cx, 3));
+
+        assert_eq!(
+            serde_json::to_string(&copy).unwrap(),
+            r#"{"a":"a","b":[1,2,3]}"#
+        );
+    }
+
+    #[test]
+    fn deserialize_cell_outside_store() {
+        let error = serde_json::from_str::<VersionedCell<'static, u32>>("1")
+            .err()
+            .unwrap();
+
+        assert!(error
+            .to_string()
+            .contains("a `VersionedCell` can only be deserialized as part of a `Store`"));
+    }
+}
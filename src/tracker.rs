@@ -0,0 +1,57 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use crate::versioned_cell::VersionedCell;
+
+/// Records the cells that are read (dereferenced) with a tracking `ReadContext`, along with the
+/// version each cell had when it was read.
+///
+/// Cells are identified by their address. Note that the recorded addresses are never dereferenced:
+/// a read cell is not necessarily part of the store's data graph (it may e.g. be a clone), so it
+/// may no longer exist by the time the reads are compared.
+#[derive(Default)]
+pub(crate) struct Tracker {
+    reads: Mutex<Reads>,
+}
+
+#[derive(Default)]
+struct Reads {
+    reads: Vec<TrackedRead>,
+    recorded: HashSet<usize>,
+}
+
+impl Tracker {
+    pub(crate) fn new() -> Self {
+        Tracker::default()
+    }
+
+    /// Records that the `cell` was read, if it was not already recorded.
+    pub(crate) fn record<'store, T: 'store>(&self, cell: &VersionedCell<'store, T>) {
+        self.extend(&[TrackedRead {
+            cell: cell as *const VersionedCell<'store, T> as usize,
+            version: cell.version(),
+        }]);
+    }
+
+    /// Records all `reads` that were not already recorded.
+    pub(crate) fn extend(&self, reads: &[TrackedRead]) {
+        let mut lock = self.reads.lock().unwrap();
+
+        for read in reads {
+            if lock.recorded.insert(read.cell) {
+                lock.reads.push(*read);
+            }
+        }
+    }
+
+    /// Returns the recorded reads in the order in which they were recorded.
+    pub(crate) fn into_reads(self) -> Vec<TrackedRead> {
+        self.reads.into_inner().unwrap().reads
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TrackedRead {
+    cell: usize,
+    version: u64,
+}
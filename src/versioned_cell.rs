@@ -1,7 +1,9 @@
 use std::cell::{Cell, UnsafeCell};
 use std::ops::{Deref, DerefMut};
-use std::{fmt, marker};
+use std::{fmt, marker, mem};
 
+use crate::journal::{Structure, TryClone};
+use crate::snapshot::{CellMap, SnapshotClone, SnapshotToken};
 use crate::store::{ReadContext, UpdateContext};
 
 // We basically reimplement RefCell, but as a type that is allowed to be Send and Sync. The store
@@ -62,7 +64,8 @@ impl fmt::Display for BorrowMutError {
 /// store data-graph is replaced in its entirety by another [VersionedCell], this can be observed
 /// later as a change in the version number of the [VersionedCell] at that location in the
 /// data-graph (a new [VersionedCell] is guaranteed to never have the same version number as any
-/// prior cell in the store at any point in time).
+/// prior cell in the store at any point in time; only copies of a cell in a
+/// [Snapshot](crate::snapshot::Snapshot) share its version number).
 pub struct VersionedCell<'store, T: 'store + ?Sized> {
     // Note: don't need atomics to track the version or borrow flag, as they can only change inside
     // an update scope, which guarantees there are never sync issues.
@@ -95,14 +98,31 @@ impl<'store, T> VersionedCell<'store, T> {
         }
     }
 
+    /// Consumes the cell and returns the inner value.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
     #[inline]
     pub fn version(&self) -> u64 {
         unsafe { *self.version.get() }
     }
 
+    /// Whether this cell was created, touched or mutably borrowed since its store had the given
+    /// `version` (see [Store::version](crate::store::Store::version)).
+    ///
+    /// Also returns `true` if the cell replaced another cell at the same location since then.
+    #[inline]
+    pub fn changed_since(&self, version: u64) -> bool {
+        self.version() >= version
+    }
+
     #[allow(unused)]
     #[inline]
     pub fn touch(&self, context: UpdateContext<'store>) {
+        context.record_version(self);
+
         let new_version = context.next_version();
 
         // SAFETY: the `UpdateContext` guarantees no other concurrent access.
@@ -123,6 +143,8 @@ impl<'store, T> VersionedCell<'store, T> {
     #[allow(unused)]
     #[inline]
     pub fn deref(&self, context: ReadContext<'store>) -> &T {
+        context.track(self);
+
         // SAFETY: the `ReadContext` guarantees the value cannot be mutably referenced for the
         // lifetime of the reference returned here.
         unsafe { &*self.value.get() }
@@ -161,9 +183,33 @@ impl<'store, T> VersionedCell<'store, T> {
     pub fn try_borrow_mut<'a>(
         &'a self,
         context: UpdateContext<'store>,
+    ) -> Result<RefMut<'a, T>, BorrowMutError> {
+        self.try_borrow_mut_with(context, UpdateContext::record_value)
+    }
+
+    /// Mutably borrows a cell whose value owns other cells, which is recorded by replacing the
+    /// value with a copy (see `Journal::record_structure`).
+    #[inline]
+    pub(crate) fn borrow_structure_mut<'a>(
+        &'a self,
+        context: UpdateContext<'store>,
+    ) -> RefMut<'a, T>
+    where
+        T: Structure,
+    {
+        self.try_borrow_mut_with(context, UpdateContext::record_structure)
+            .expect("already borrowed")
+    }
+
+    #[inline]
+    fn try_borrow_mut_with<'a>(
+        &'a self,
+        context: UpdateContext<'store>,
+        record: fn(&UpdateContext<'store>, &VersionedCell<'store, T>),
     ) -> Result<RefMut<'a, T>, BorrowMutError> {
         match BorrowRefMut::new(&self.borrow) {
             Some(b) => {
+                record(&context, self);
                 self.touch(context);
 
                 // SAFETY: the combination of the `UpdateContext` and `BorrowRefMut` guarantees
@@ -176,12 +222,204 @@ impl<'store, T> VersionedCell<'store, T> {
             None => Err(BorrowMutError {}),
         }
     }
+
+    /// Returns a reference to the inner value without requiring a context.
+    ///
+    /// # Safety
+    ///
+    /// The value must not be mutably borrowed for the lifetime of the returned reference.
+    #[inline]
+    pub(crate) unsafe fn value_unchecked(&self) -> &T {
+        &*self.value.get()
+    }
+
+    /// Returns a pointer to the cell's value.
+    pub(crate) fn value_ptr(this: *const Self) -> *const T {
+        // SAFETY: only computes the address of the value, does not access the cell.
+        unsafe { UnsafeCell::raw_get(std::ptr::addr_of!((*this).value)) }
+    }
+
+    /// Returns a new cell with the same version as this cell and a clone of its value, or `None` if
+    /// the value cannot be cloned (see `TryClone`).
+    ///
+    /// # Safety
+    ///
+    /// The value must not be mutably borrowed.
+    pub(crate) unsafe fn try_clone_unchecked(&self) -> Option<Self> {
+        Some(VersionedCell {
+            version: UnsafeCell::new(self.version()),
+            borrow: UnsafeCell::new(UNUSED),
+            value: UnsafeCell::new(self.value_unchecked().try_clone()?),
+            _marker: marker::PhantomData,
+        })
+    }
+
+    /// Replaces the inner value with the given `value`, and returns the previous value.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called inside an update scope, while the value is not borrowed.
+    pub(crate) unsafe fn replace_unchecked(&self, value: T) -> T {
+        mem::replace(&mut *self.value.get(), value)
+    }
+
+    /// Resets the cell's version to the given `version` and, if a `value` is given, replaces the
+    /// inner value.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called inside an update scope, while the cell is not borrowed.
+    pub(crate) unsafe fn restore_unchecked(&self, version: u64, value: Option<T>) {
+        debug_assert!(*self.borrow.get() == UNUSED);
+
+        *self.version.get() = version;
+
+        if let Some(value) = value {
+            *self.value.get() = value;
+        }
+    }
+}
+
+impl<T: SnapshotClone> SnapshotClone for VersionedCell<'_, T> {
+    /// Returns a copy of the cell, that holds a copy of the cell's value and has the same version
+    /// number as the cell.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cell is currently mutably borrowed.
+    fn snapshot_clone(&self, token: SnapshotToken<'_>) -> Self {
+        // SAFETY: a `VersionedCell` can only be accessed inside a read scope, in which the borrow
+        // flag is never written to, or inside an update scope, which is confined to a single
+        // thread. We only read the borrow flag here, so there is no data race in either case.
+        if is_writing(unsafe { *self.borrow.get() }) {
+            panic!("already mutably borrowed");
+        }
+
+        VersionedCell {
+            version: UnsafeCell::new(self.version()),
+            borrow: UnsafeCell::new(UNUSED),
+            // SAFETY: the value is not mutably borrowed, see above.
+            value: UnsafeCell::new(unsafe { self.value_unchecked() }.snapshot_clone(token)),
+            _marker: marker::PhantomData,
+        }
+    }
+
+    fn map_cells<'a, 'b>(&'a self, copy: &'b Self, map: &mut CellMap<'a, 'b>) {
+        map.insert(self, copy);
+
+        // SAFETY: cells are only mapped while no update scope is running, so neither value is
+        // mutably borrowed.
+        unsafe { self.value_unchecked() }.map_cells(unsafe { copy.value_unchecked() }, map);
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for VersionedCell<'_, T> {
+    /// Serializes the cell as its inner value.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        // SAFETY: see `snapshot_clone`.
+        if is_writing(unsafe { *self.borrow.get() }) {
+            return Err(serde::ser::Error::custom("already mutably borrowed"));
+        }
+
+        // SAFETY: the value is not mutably borrowed, see above.
+        unsafe { self.value_unchecked() }.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, 'store, T: serde::Deserialize<'de>> serde::Deserialize<'de> for VersionedCell<'store, T> {
+    /// Deserializes a new cell from its inner value.
+    ///
+    /// A cell can only be deserialized as part of the data graph of a
+    /// [Store](crate::store::Store) that is being deserialized; deserializing a cell in any other
+    /// context results in an error.
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = T::deserialize(deserializer)?;
+
+        // SAFETY: the cell is only ever created while a store is being deserialized.
+        let context = unsafe { UpdateContext::deserialization_context() }.ok_or_else(|| {
+            serde::de::Error::custom(
+                "a `VersionedCell` can only be deserialized as part of a `Store`",
+            )
+        })?;
+
+        Ok(VersionedCell::new(context, value))
+    }
 }
 
 // SAFETY: all `UnsafeCell`'s inside are only ever written to inside an update scope, which ensures
 // writes are synchronized.
 unsafe impl<T> Sync for VersionedCell<'_, T> {}
 
+/// Implemented for all types that do not own or reference a [VersionedCell].
+///
+/// # Safety
+///
+/// Implemented automatically. Implementing it manually for a type that does own or reference a
+/// [VersionedCell] allows that cell to be shared with a [Snapshot](crate::snapshot::Snapshot), or
+/// to be moved or dropped while a journal still records it.
+pub unsafe auto trait CellFree {}
+
+impl<T: ?Sized> !CellFree for VersionedCell<'_, T> {}
+
+/// Implemented for all types in which a [VersionedCell] can only be moved or dropped by mutably
+/// borrowing the [VersionedCell] that owns it.
+///
+/// To roll back an update scope, a store records the state of every [VersionedCell] that the scope
+/// modifies, and later restores the cells it recorded. This requires that the recorded cells are
+/// still there: a store can notice when a cell that owns other cells is mutably borrowed, but not
+/// when cells are moved or dropped through interior mutability outside of a [VersionedCell] (e.g.
+/// through a `Mutex<Vec<VersionedCell<'store, T>>>`). Such types therefore do not implement
+/// [CellStable], and neither does a type that contains them. Interior mutability of values that do
+/// not own any cells (e.g. a `Mutex<u32>`) is allowed.
+///
+/// Rolling back an update scope with [Store::try_update] or [Store::set_panic_policy], or keeping
+/// an undo history with [Store::set_history_depth] requires the store's data to implement
+/// [CellStable]:
+///
+/// ```compile_fail,E0277
+/// # use std::sync::Mutex;
+/// # use viemo::store::Store;
+/// # use viemo::versioned_cell::VersionedCell;
+/// struct Root<'store> {
+///     cells: Mutex<Vec<VersionedCell<'store, String>>>,
+/// }
+///
+/// viemo::gen_type_constructor!(Root, RootTC);
+///
+/// let store = Store::<RootTC>::initialize(|cx| Root {
+///     cells: Mutex::new(vec![VersionedCell::new(cx, String::from("a"))]),
+/// });
+///
+/// let result: Result<(), ()> = store.try_update(|root, cx| {
+///     root.cells.lock().unwrap()[0].borrow_mut(cx).push('b');
+///     root.cells.lock().unwrap().clear();
+///
+///     Err(())
+/// });
+/// ```
+///
+/// # Safety
+///
+/// Implemented automatically. Implementing it manually for a type that allows cells to be moved or
+/// dropped through a shared reference allows a store to restore a cell that no longer exists.
+///
+/// [Store::try_update]: crate::store::Store::try_update
+/// [Store::set_panic_policy]: crate::store::Store::set_panic_policy
+/// [Store::set_history_depth]: crate::store::Store::set_history_depth
+pub unsafe auto trait CellStable {}
+
+unsafe impl<T: ?Sized + CellFree> CellStable for UnsafeCell<T> {}
+
+unsafe impl<T: ?Sized + CellStable> CellStable for VersionedCell<'_, T> {}
+
 // Modified from `core::cell`.
 
 type BorrowFlag = isize;
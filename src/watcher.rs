@@ -1,12 +1,28 @@
 use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use futures::Stream;
 
 use crate::memo::{Memo, MemoLifetime};
-use crate::store::{OnUpdate, ReadContext, Store};
+use crate::store::{OnUpdate, ReadContext, Store, Subscription};
 use crate::TypeConstructor;
 
+/// A stream that calls `f` with the value of a memo whenever the store updates and the memo
+/// changed (and once initially), and yields the outputs of `f` that are not `None`.
+///
+/// An output of `None` does not end the stream; the stream only ends when the store is dropped.
+///
+/// To watch more than one memo, pass a tuple of memos (or an array or vector of memos of the same
+/// type); `f` then receives a tuple of their values. Tuples can be nested to watch any number of
+/// memos:
+///
+/// ```ignore
+/// let watcher = Watcher::new(&store, (memo_0, memo_1, (memo_2, memo_3)), |(a, b, (c, d)), cx| {
+///     ...
+/// });
+/// ```
 pub struct Watcher<C, M, F>
 where
     C: TypeConstructor,
@@ -65,143 +81,322 @@ where
         if *initial {
             *initial = false;
 
-            return store.with(|root, cx| {
+            // Start listening for updates before the initial refresh, so that updates that occur
+            // after it are not missed if it produces an output.
+            if let Poll::Ready(None) = Pin::new(&mut *on_update).poll_next(cx) {
+                return Poll::Ready(None);
+            }
+
+            let output = store.with(|root, cx| {
                 let refreshed = memo.refresh_unchecked(root, cx);
 
-                Poll::Ready(f(refreshed.value, cx))
+                f(refreshed.value, cx)
             });
+
+            if output.is_some() {
+                return Poll::Ready(output);
+            }
         }
 
-        match Pin::new(on_update).poll_next(cx) {
-            Poll::Ready(Some(_)) => store.with(|root, cx| {
-                let refreshed = memo.refresh_unchecked(root, cx);
+        // Keep polling for updates until `f` produces an output, so that the task's waker is
+        // registered with `on_update` whenever this returns `Poll::Pending`.
+        loop {
+            match Pin::new(&mut *on_update).poll_next(cx) {
+                Poll::Ready(Some(_)) => {
+                    let output = store.with(|root, cx| {
+                        let refreshed = memo.refresh_unchecked(root, cx);
 
-                if refreshed.is_changed {
-                    Poll::Ready(f(refreshed.value, cx))
-                } else {
-                    Poll::Pending
+                        if refreshed.is_changed {
+                            f(refreshed.value, cx)
+                        } else {
+                            None
+                        }
+                    });
+
+                    if output.is_some() {
+                        return Poll::Ready(output);
+                    }
                 }
-            }),
-            Poll::Ready(None) => Poll::Ready(None),
-            Poll::Pending => Poll::Pending,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
         }
     }
 }
 
-macro_rules! watcher {
-    ($watcher:ident, $($memo:ident $name:literal),*) => {
-        #[allow(non_snake_case)]
-        pub struct $watcher<C, $($memo,)* F>
-        where
-            C: TypeConstructor,
-        {
-            store: Store<C>,
-            f: F,
-            on_update: OnUpdate,
-            $($memo: $memo,)*
-            initial: bool
+/// A blocking counterpart to [Watcher] for threads that don't run an async executor.
+///
+/// Calls `f` with the value of a memo whenever the store updates and the memo changed (and once
+/// initially), like a [Watcher]. Instead of yielding the outputs of `f` as a stream,
+/// [recv](Self::recv) parks the current thread until an output is available. As with a [Watcher],
+/// outputs of `None` are skipped. A [BlockingWatcher] is also an [Iterator] that never ends; each
+/// call to `next` blocks like [recv](Self::recv).
+///
+/// ```ignore
+/// let watcher = BlockingWatcher::new(&store, memo, |value, cx| Some(value.deref(cx).clone()));
+///
+/// for value in watcher {
+///     println!("{:?}", value);
+/// }
+/// ```
+pub struct BlockingWatcher<C, M, F>
+where
+    C: TypeConstructor,
+{
+    store: Store<C>,
+    f: F,
+    signal: Arc<Signal>,
+    _subscription: Subscription,
+    memo: M,
+    initial: bool,
+}
+
+impl<C, M, F, O> BlockingWatcher<C, M, F>
+where
+    C: TypeConstructor,
+    M: Memo<RootTC = C>,
+    F: for<'a, 'b, 'store> Fn(
+        <M as MemoLifetime<'a, 'b, 'store>>::Value,
+        ReadContext<'store>,
+    ) -> Option<O>,
+{
+    pub fn new(store: &Store<C>, memo: M, f: F) -> Self {
+        if memo.store_id() != store.id() {
+            panic!("memo is not associated with the store passed to the watcher")
+        }
+
+        let signal = Arc::new(Signal {
+            pending: Mutex::new(false),
+            condvar: Condvar::new(),
+        });
+
+        let subscription = {
+            let signal = signal.clone();
+
+            store.subscribe(move || signal.notify())
+        };
+
+        BlockingWatcher {
+            store: store.clone(),
+            f,
+            signal,
+            _subscription: subscription,
+            memo,
+            initial: true,
         }
+    }
 
-        #[allow(non_snake_case)]
-        impl<C, $($memo,)* F, O> $watcher<C, $($memo,)* F>
-        where
-            C: TypeConstructor,
-            $($memo: Memo<RootTC = C>,)*
-            F: for<'a, 'b, 'store> Fn(
-                (
-                    $(<$memo as MemoLifetime<'a, 'b, 'store>>::Value,)*
-                ),
-                ReadContext<'store>,
-            ) -> Option<O>,
-        {
-            pub fn new(store: &Store<C>, $($memo: $memo,)* f: F) -> Self {
-                $(
-                    if $memo.store_id() != store.id() {
-                        panic!("{} is not associated with the store passed to the watcher", $name)
-                    }
-                )*
-
-                $watcher {
-                    on_update: store.on_update(),
-                    f,
-                    store: store.clone(),
-                    $($memo,)*
-                    initial: true
-                }
+    /// Blocks the current thread until `f` produces an output and returns it.
+    pub fn recv(&mut self) -> O {
+        loop {
+            if let Some(output) = self.recv_until(None) {
+                return output;
             }
         }
+    }
 
-        #[allow(non_snake_case)]
-        impl<C, $($memo,)* F, O> Stream for $watcher<C, $($memo,)* F>
-        where
-            C: TypeConstructor,
-            $($memo: Memo<RootTC = C>,)*
-            F: for<'a, 'b, 'store> Fn(
-                (
-                    $(<$memo as MemoLifetime<'a, 'b, 'store>>::Value,)*
-                ),
-                ReadContext<'store>,
-            ) -> Option<O>,
-        {
-            type Item = O;
-
-            fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-                let $watcher {
-                    store,
-                    f,
-                    on_update,
-                    $($memo,)*
-                    initial
-                } = unsafe { self.get_unchecked_mut() };
-
-                if *initial {
-                    *initial = false;
-
-                    return store.with(|root, cx| {
-                        $(let $memo = $memo.refresh_unchecked(root, cx);)*
-
-                        Poll::Ready(f(($($memo.value),*), cx))
-                    });
+    /// Blocks the current thread until `f` produces an output, or until the `timeout` elapses.
+    ///
+    /// Returns `None` if the timeout elapsed. A `timeout` too large to represent as a deadline
+    /// blocks like [recv](Self::recv).
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Option<O> {
+        self.recv_until(Instant::now().checked_add(timeout))
+    }
+
+    /// Returns the output of `f` if the store updated since the last output and `f` produces an
+    /// output, without blocking.
+    pub fn try_recv(&mut self) -> Option<O> {
+        self.recv_until(Some(Instant::now()))
+    }
+
+    fn recv_until(&mut self, deadline: Option<Instant>) -> Option<O> {
+        let BlockingWatcher {
+            store,
+            f,
+            signal,
+            memo,
+            initial,
+            ..
+        } = self;
+
+        if *initial {
+            *initial = false;
+
+            let output = store.with(|root, cx| {
+                let refreshed = memo.refresh_unchecked(root, cx);
+
+                f(refreshed.value, cx)
+            });
+
+            if output.is_some() {
+                return output;
+            }
+        }
+
+        while signal.wait(deadline) {
+            let output = store.with(|root, cx| {
+                let refreshed = memo.refresh_unchecked(root, cx);
+
+                if refreshed.is_changed {
+                    f(refreshed.value, cx)
+                } else {
+                    None
                 }
+            });
 
-                match Pin::new(on_update).poll_next(cx) {
-                    Poll::Ready(Some(_)) => store.with(|root, cx| {
-                        $(let $memo = $memo.refresh_unchecked(root, cx);)*
+            if output.is_some() {
+                return output;
+            }
+        }
 
-                        let mut is_changed = false;
+        None
+    }
+}
 
-                        $(
-                            if $memo.is_changed {
-                                is_changed = true;
-                            }
-                        )*
+impl<C, M, F, O> Iterator for BlockingWatcher<C, M, F>
+where
+    C: TypeConstructor,
+    M: Memo<RootTC = C>,
+    F: for<'a, 'b, 'store> Fn(
+        <M as MemoLifetime<'a, 'b, 'store>>::Value,
+        ReadContext<'store>,
+    ) -> Option<O>,
+{
+    type Item = O;
 
-                        if is_changed {
-                            Poll::Ready(f(($($memo.value),*), cx))
-                        } else {
-                            Poll::Pending
-                        }
-                    }),
-                    Poll::Ready(None) => Poll::Ready(None),
-                    Poll::Pending => Poll::Pending,
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.recv())
+    }
+}
+
+/// Wakes a [BlockingWatcher] when its store updates.
+struct Signal {
+    pending: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl Signal {
+    fn notify(&self) {
+        *self.pending.lock().unwrap() = true;
+
+        self.condvar.notify_all();
+    }
+
+    /// Blocks until the store updated (since the last time this returned `true`) or until the
+    /// `deadline` passes.
+    ///
+    /// Returns `false` if the deadline passed.
+    fn wait(&self, deadline: Option<Instant>) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+
+        while !*pending {
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+
+                    if now >= deadline {
+                        return false;
+                    }
+
+                    pending = self
+                        .condvar
+                        .wait_timeout(pending, deadline - now)
+                        .unwrap()
+                        .0;
                 }
+                None => pending = self.condvar.wait(pending).unwrap(),
             }
         }
+
+        *pending = false;
+
+        true
     }
 }
 
-watcher!(Watcher2, M0 "memo `0`", M1 "memo `1`");
-watcher!(Watcher3, M0 "memo `0`", M1 "memo `1`", M2 "memo `2`");
-watcher!(Watcher4, M0 "memo `0`", M1 "memo `1`", M2 "memo `2`", M3 "memo `3`");
-watcher!(Watcher5, M0 "memo `0`", M1 "memo `1`", M2 "memo `2`", M3 "memo `3`", M4 "memo `4`");
-watcher!(Watcher6, M0 "memo `0`", M1 "memo `1`", M2 "memo `2`", M3 "memo `3`", M4 "memo `4`", M5 "memo `5`");
-watcher!(Watcher7, M0 "memo `0`", M1 "memo `1`", M2 "memo `2`", M3 "memo `3`", M4 "memo `4`", M5 "memo `5`", M6 "memo `6`");
-watcher!(Watcher8, M0 "memo `0`", M1 "memo `1`", M2 "memo `2`", M3 "memo `3`", M4 "memo `4`", M5 "memo `5`", M6 "memo `6`", M7 "memo `7`");
-watcher!(Watcher9, M0 "memo `0`", M1 "memo `1`", M2 "memo `2`", M3 "memo `3`", M4 "memo `4`", M5 "memo `5`", M6 "memo `6`", M7 "memo `7`", M8 "memo `8`");
-watcher!(Watcher10, M0 "memo `0`", M1 "memo `1`", M2 "memo `2`", M3 "memo `3`", M4 "memo `4`", M5 "memo `5`", M6 "memo `6`", M7 "memo `7`", M8 "memo `8`", M9 "memo `9`");
-watcher!(Watcher11, M0 "memo `0`", M1 "memo `1`", M2 "memo `2`", M3 "memo `3`", M4 "memo `4`", M5 "memo `5`", M6 "memo `6`", M7 "memo `7`", M8 "memo `8`", M9 "memo `9`", M10 "memo `10`");
-watcher!(Watcher12, M0 "memo `0`", M1 "memo `1`", M2 "memo `2`", M3 "memo `3`", M4 "memo `4`", M5 "memo `5`", M6 "memo `6`", M7 "memo `7`", M8 "memo `8`", M9 "memo `9`", M10 "memo `10`", M11 "memo `11`");
-watcher!(Watcher13, M0 "memo `0`", M1 "memo `1`", M2 "memo `2`", M3 "memo `3`", M4 "memo `4`", M5 "memo `5`", M6 "memo `6`", M7 "memo `7`", M8 "memo `8`", M9 "memo `9`", M10 "memo `10`", M11 "memo `11`", M12 "memo `12`");
-watcher!(Watcher14, M0 "memo `0`", M1 "memo `1`", M2 "memo `2`", M3 "memo `3`", M4 "memo `4`", M5 "memo `5`", M6 "memo `6`", M7 "memo `7`", M8 "memo `8`", M9 "memo `9`", M10 "memo `10`", M11 "memo `11`", M12 "memo `12`", M13 "memo `13`");
-watcher!(Watcher15, M0 "memo `0`", M1 "memo `1`", M2 "memo `2`", M3 "memo `3`", M4 "memo `4`", M5 "memo `5`", M6 "memo `6`", M7 "memo `7`", M8 "memo `8`", M9 "memo `9`", M10 "memo `10`", M11 "memo `11`", M12 "memo `12`", M13 "memo `13`", M14 "memo `14`");
-watcher!(Watcher16, M0 "memo `0`", M1 "memo `1`", M2 "memo `2`", M3 "memo `3`", M4 "memo `4`", M5 "memo `5`", M6 "memo `6`", M7 "memo `7`", M8 "memo `8`", M9 "memo `9`", M10 "memo `10`", M11 "memo `11`", M12 "memo `12`", M13 "memo `13`", M14 "memo `14`", M15 "memo `15`");
+macro_rules! watcher_alias {
+    ($watcher:ident, $($memo:ident),*) => {
+        /// A [Watcher] for a tuple of memos.
+        pub type $watcher<C, $($memo,)* F> = Watcher<C, ($($memo,)*), F>;
+    };
+}
+
+watcher_alias!(Watcher2, M0, M1);
+watcher_alias!(Watcher3, M0, M1, M2);
+watcher_alias!(Watcher4, M0, M1, M2, M3);
+watcher_alias!(Watcher5, M0, M1, M2, M3, M4);
+watcher_alias!(Watcher6, M0, M1, M2, M3, M4, M5);
+watcher_alias!(Watcher7, M0, M1, M2, M3, M4, M5, M6);
+watcher_alias!(Watcher8, M0, M1, M2, M3, M4, M5, M6, M7);
+watcher_alias!(Watcher9, M0, M1, M2, M3, M4, M5, M6, M7, M8);
+watcher_alias!(Watcher10, M0, M1, M2, M3, M4, M5, M6, M7, M8, M9);
+watcher_alias!(Watcher11, M0, M1, M2, M3, M4, M5, M6, M7, M8, M9, M10);
+watcher_alias!(Watcher12, M0, M1, M2, M3, M4, M5, M6, M7, M8, M9, M10, M11);
+watcher_alias!(Watcher13, M0, M1, M2, M3, M4, M5, M6, M7, M8, M9, M10, M11, M12);
+watcher_alias!(Watcher14, M0, M1, M2, M3, M4, M5, M6, M7, M8, M9, M10, M11, M12, M13);
+watcher_alias!(Watcher15, M0, M1, M2, M3, M4, M5, M6, M7, M8, M9, M10, M11, M12, M13, M14);
+watcher_alias!(Watcher16, M0, M1, M2, M3, M4, M5, M6, M7, M8, M9, M10, M11, M12, M13, M14, M15);
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use futures::StreamExt;
+
+    use std::time::Duration;
+
+    use super::{BlockingWatcher, Watcher};
+    use crate::memo::CellMemo;
+    use crate::store::Store;
+    use crate::versioned_cell::VersionedCell;
+
+    struct Root<'store> {
+        a: VersionedCell<'store, u32>,
+    }
+
+    crate::gen_type_constructor!(Root, RootTC);
+
+    #[test]
+    fn watcher_skips_none_outputs() {
+        let store = Store::<RootTC>::initialize(|cx| Root {
+            a: VersionedCell::new(cx, 1),
+        });
+
+        let memo = CellMemo::new(&store, |root: &Root, _| &root.a);
+        let mut watcher = Watcher::new(&store, memo, |cell, cx| {
+            let value = *cell.deref(cx);
+
+            (value % 2 == 0).then_some(value)
+        });
+
+        store.update(|root, cx| *root.a.borrow_mut(cx) = 3);
+        store.update(|root, cx| *root.a.borrow_mut(cx) = 4);
+
+        assert_eq!(block_on(watcher.next()), Some(4));
+
+        store.update(|root, cx| *root.a.borrow_mut(cx) = 5);
+        store.update(|root, cx| *root.a.borrow_mut(cx) = 6);
+
+        assert_eq!(block_on(watcher.next()), Some(6));
+    }
+
+    #[test]
+    fn blocking_watcher_skips_none_outputs() {
+        let store = Store::<RootTC>::initialize(|cx| Root {
+            a: VersionedCell::new(cx, 1),
+        });
+
+        let memo = CellMemo::new(&store, |root: &Root, _| &root.a);
+        let mut watcher = BlockingWatcher::new(&store, memo, |cell, cx| {
+            let value = *cell.deref(cx);
+
+            (value % 2 == 0).then_some(value)
+        });
+
+        assert_eq!(watcher.try_recv(), None);
+
+        store.update(|root, cx| *root.a.borrow_mut(cx) = 3);
+        store.update(|root, cx| *root.a.borrow_mut(cx) = 4);
+
+        assert_eq!(watcher.recv_timeout(Duration::MAX), Some(4));
+    }
+}
@@ -0,0 +1,129 @@
+use viemo::memo::Memo;
+use viemo::select::NodeSelector;
+use viemo::store::Store;
+use viemo::versioned_cell::VersionedCell;
+use viemo_derive::{Selectors, TypeConstructor};
+
+trait Backend: Send + Sync + 'static {
+    type Content: Send + Sync + 'static;
+}
+
+struct Text;
+
+impl Backend for Text {
+    type Content = String;
+}
+
+#[derive(TypeConstructor, Selectors)]
+#[type_constructor(store = 's)]
+struct Document<'a, 's, B>
+where
+    B: Backend,
+{
+    name: &'a str,
+    content: VersionedCell<'s, B::Content>,
+    paragraph: VersionedCell<'s, Paragraph<'s>>,
+    paragraphs: Vec<VersionedCell<'s, Paragraph<'s>>>,
+    title: Option<VersionedCell<'s, String>>,
+    #[selectors(skip)]
+    revision: VersionedCell<'s, u32>,
+}
+
+#[derive(TypeConstructor, Selectors)]
+struct Paragraph<'store> {
+    text: VersionedCell<'store, String>,
+}
+
+type DocumentTC = Document<'static, 'static, Text>;
+
+// If `#[selectors(skip)]` did not skip the field, calls to `revision` would be ambiguous between
+// this trait and the generated one.
+trait RevisionSelector {
+    fn revision(&self) -> u32;
+}
+
+impl RevisionSelector for NodeSelector<DocumentTC> {
+    fn revision(&self) -> u32 {
+        0
+    }
+}
+
+fn store() -> Store<DocumentTC> {
+    Store::initialize(|cx| Document {
+        name: "document",
+        content: VersionedCell::new(cx, String::from("content")),
+        paragraph: VersionedCell::new(
+            cx,
+            Paragraph {
+                text: VersionedCell::new(cx, String::from("paragraph")),
+            },
+        ),
+        paragraphs: Vec::new(),
+        title: None,
+        revision: VersionedCell::new(cx, 0),
+    })
+}
+
+#[test]
+fn type_constructor_with_generics_and_multiple_lifetimes() {
+    let store = store();
+
+    store.update(|root, cx| *root.revision.borrow_mut(cx) += 1);
+
+    store.with(|root, cx| {
+        assert_eq!(root.name, "document");
+        assert_eq!(root.content.deref(cx), "content");
+        assert_eq!(root.paragraph.deref(cx).text.deref(cx), "paragraph");
+        assert!(root.paragraphs.is_empty());
+        assert!(root.title.is_none());
+        assert_eq!(*root.revision.deref(cx), 1);
+    });
+}
+
+#[test]
+fn selectors_for_cell_fields() {
+    let store = store();
+    let root = NodeSelector::<DocumentTC>::root();
+
+    let mut content = root.content().memo(&store);
+    let mut paragraphs = root.paragraphs().memo(&store);
+    let mut title = root.title().memo(&store);
+
+    store.update(|root, cx| root.content.borrow_mut(cx).push('!'));
+
+    store.with(|root, cx| {
+        let refresh = content.refresh(root, cx);
+
+        assert!(refresh.is_changed);
+        assert_eq!(refresh.value.deref(cx), "content!");
+        assert!(!paragraphs.refresh(root, cx).is_changed);
+        assert!(!title.refresh(root, cx).is_changed);
+    });
+
+    assert_eq!(root.revision(), 0);
+}
+
+#[test]
+fn selectors_for_nested_nodes() {
+    let store = store();
+    let root = NodeSelector::<DocumentTC>::root();
+
+    let mut text = root.paragraph().node().text().memo(&store);
+
+    store.with(|root, cx| assert!(!text.refresh(root, cx).is_changed));
+
+    store.update(|root, cx| {
+        root.paragraph
+            .borrow(cx)
+            .text
+            .borrow_mut(cx)
+            .push_str(" text")
+    });
+
+    store.with(|root, cx| {
+        let refresh = text.refresh(root, cx);
+
+        assert!(refresh.is_changed);
+        assert_eq!(refresh.value.deref(cx), "paragraph text");
+    });
+}
@@ -0,0 +1,116 @@
+extern crate proc_macro;
+
+mod selectors;
+mod snapshot_clone;
+mod type_constructor;
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput};
+
+/// Derives `TypeConstructor` for a type that is generic over a store lifetime.
+///
+/// The trait is implemented for the type itself, with all of its lifetime parameters set to
+/// `'static`; `Type<'store>` is the type with its store lifetime set to `'store`. This means the
+/// type can be used directly as a store's root or node type constructor, e.g.
+/// `Store<MyRoot<'static>>`.
+///
+/// If the type has a single lifetime parameter, or a lifetime parameter named `'store`, that
+/// lifetime is the store lifetime. Otherwise, the store lifetime must be marked with a
+/// `#[type_constructor(store = 'a)]` attribute. Any other lifetime parameters are set to `'static`
+/// in `Type<'store>` as well. Type parameters must be `'static`.
+///
+/// # Example
+///
+/// ```ignore
+/// #[derive(TypeConstructor)]
+/// #[type_constructor(store = 's)]
+/// struct Document<'a, 's, B: Backend> {
+///     name: &'a str,
+///     content: VersionedCell<'s, B::Content>,
+/// }
+///
+/// type DocumentStore<B> = Store<Document<'static, 'static, B>>;
+/// ```
+#[proc_macro_derive(TypeConstructor, attributes(type_constructor))]
+pub fn derive_type_constructor(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    type_constructor::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives a trait with selectors for the cell fields of a store node.
+///
+/// For a type `MyNode`, this generates a `MyNodeSelectors` trait that is implemented for
+/// `NodeSelector<R, MyNode<'static>>`, with a method for every field of one of the following
+/// types, which returns the corresponding selector:
+///
+/// - `VersionedCell<'store, T>`: `CellSelector<R, T>`
+/// - `Option<VersionedCell<'store, T>>`: `OptionCellSelector<R, T>`
+/// - `Vec<VersionedCell<'store, T>>`: `CellSliceSelector<R, T>`
+///
+/// If `T` mentions the store lifetime, `T` is a node and the methods instead return a
+/// `NodeCellSelector`, `OptionNodeCellSelector` or `NodeSliceSelector` respectively, with `T`'s
+/// lifetimes set to `'static` as its type constructor. Node types must implement
+/// `TypeConstructor` in the way `#[derive(TypeConstructor)]` implements it. Other fields are
+/// ignored, as are fields marked with `#[selectors(skip)]`.
+///
+/// The store lifetime is determined in the same way as for `#[derive(TypeConstructor)]`.
+///
+/// # Example
+///
+/// ```ignore
+/// #[derive(TypeConstructor, Selectors)]
+/// struct MyRoot<'store> {
+///     node_element: VersionedCell<'store, NodeElement<'store>>,
+/// }
+///
+/// #[derive(TypeConstructor, Selectors)]
+/// struct NodeElement<'store> {
+///     element: VersionedCell<'store, Element>,
+/// }
+///
+/// let memo = NodeSelector::<MyRoot<'static>>::root()
+///     .node_element()
+///     .node()
+///     .element()
+///     .memo(&store);
+/// ```
+#[proc_macro_derive(Selectors, attributes(selectors))]
+pub fn derive_selectors(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    selectors::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `SnapshotClone` for a struct or enum by copying every field, and implements
+/// `SnapshotClone::map_cells` by forwarding to every field that is not marked with
+/// `#[snapshot(clone)]`.
+///
+/// Every field must implement `SnapshotClone`, except fields marked with `#[snapshot(clone)]`,
+/// which are copied with `Clone` instead. Such fields must implement `CellFree`, as they would
+/// otherwise share the `VersionedCell`s they own with the snapshot. Type parameters are required to
+/// implement `SnapshotClone`.
+///
+/// # Example
+///
+/// ```ignore
+/// #[derive(TypeConstructor, SnapshotClone)]
+/// struct MyRoot<'store> {
+///     title: VersionedCell<'store, String>,
+///     rows: VersionedVec<'store, Row>,
+///     #[snapshot(clone)]
+///     settings: Settings,
+/// }
+/// ```
+#[proc_macro_derive(SnapshotClone, attributes(snapshot))]
+pub fn derive_snapshot_clone(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    snapshot_clone::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,285 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::visit::Visit;
+use syn::visit_mut::VisitMut;
+use syn::{
+    Data, DeriveInput, Error, Fields, GenericArgument, GenericParam, Lifetime, PathArguments, Type,
+};
+
+use crate::type_constructor::{store_lifetime, ReplaceLifetimes};
+
+pub fn expand(input: &DeriveInput) -> Result<TokenStream, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "`Selectors` can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "`Selectors` can only be derived for structs with named fields",
+            ))
+        }
+    };
+
+    let store_lifetime = store_lifetime(input)?.ok_or_else(|| {
+        Error::new_spanned(
+            &input.ident,
+            "`Selectors` can only be derived for types with a store lifetime",
+        )
+    })?;
+
+    let vis = &input.vis;
+    let ident = &input.ident;
+    let trait_ident = format_ident!("{}Selectors", ident);
+
+    let mut replace_lifetimes = ReplaceLifetimes {
+        lifetimes: input
+            .generics
+            .lifetimes()
+            .map(|def| def.lifetime.clone())
+            .collect(),
+    };
+
+    let mut params = Vec::new();
+    let mut args = Vec::new();
+    let mut self_args = Vec::new();
+
+    for param in &input.generics.params {
+        match param {
+            GenericParam::Lifetime(_) => self_args.push(quote!('static)),
+            GenericParam::Type(def) => {
+                let mut def = def.clone();
+
+                def.attrs.clear();
+                def.eq_token = None;
+                def.default = None;
+                def.bounds.push(syn::parse_quote!('static));
+                replace_lifetimes.visit_type_param_mut(&mut def);
+
+                let ident = &def.ident;
+
+                self_args.push(quote!(#ident));
+                args.push(quote!(#ident));
+                params.push(quote!(#def));
+            }
+            GenericParam::Const(def) => {
+                let mut def = def.clone();
+
+                def.attrs.clear();
+                def.eq_token = None;
+                def.default = None;
+
+                let ident = &def.ident;
+
+                self_args.push(quote!(#ident));
+                args.push(quote!(#ident));
+                params.push(quote!(#def));
+            }
+        }
+    }
+
+    let mut where_clause = input.generics.where_clause.clone();
+
+    if let Some(where_clause) = &mut where_clause {
+        replace_lifetimes.visit_where_clause_mut(where_clause);
+    }
+
+    let where_predicates = where_clause.map(|where_clause| where_clause.predicates);
+
+    let mut signatures = Vec::new();
+    let mut methods = Vec::new();
+
+    for field in fields {
+        if is_skipped(field)? {
+            continue;
+        }
+
+        let Some((kind, cell_type)) = field_kind(&field.ty) else {
+            continue;
+        };
+
+        let mut value_type = cell_type.clone();
+        let is_node = mentions_lifetime(&value_type, &store_lifetime);
+
+        replace_lifetimes.visit_type_mut(&mut value_type);
+
+        let field_ident = field.ident.as_ref().unwrap();
+        let doc = format!("Selects the `{}` field.", field_ident);
+
+        let (selector, map, select) = match (kind, is_node) {
+            (FieldKind::Cell, false) => (
+                quote!(CellSelector),
+                quote!(cell),
+                quote!(&node.#field_ident),
+            ),
+            (FieldKind::Cell, true) => (
+                quote!(NodeCellSelector),
+                quote!(node_cell),
+                quote!(&node.#field_ident),
+            ),
+            (FieldKind::Option, false) => (
+                quote!(OptionCellSelector),
+                quote!(option_cell),
+                quote!(node.#field_ident.as_ref()),
+            ),
+            (FieldKind::Option, true) => (
+                quote!(OptionNodeCellSelector),
+                quote!(option_node_cell),
+                quote!(node.#field_ident.as_ref()),
+            ),
+            (FieldKind::Vec, false) => (
+                quote!(CellSliceSelector),
+                quote!(cell_slice),
+                quote!(node.#field_ident.as_slice()),
+            ),
+            (FieldKind::Vec, true) => (
+                quote!(NodeSliceSelector),
+                quote!(node_slice),
+                quote!(node.#field_ident.as_slice()),
+            ),
+        };
+
+        let output = quote!(::viemo::select::#selector<R, #value_type>);
+
+        signatures.push(quote! {
+            #[doc = #doc]
+            fn #field_ident(&self) -> #output;
+        });
+
+        methods.push(quote! {
+            fn #field_ident(&self) -> #output {
+                ::viemo::select::NodeSelector::#map(self, |node, _| #select)
+            }
+        });
+    }
+
+    let trait_doc = format!(
+        "Selectors for the fields of a `{}` node, generated by `#[derive(Selectors)]`.",
+        ident
+    );
+
+    Ok(quote! {
+        #[doc = #trait_doc]
+        #vis trait #trait_ident<R, #(#params),*>
+        where
+            R: ::viemo::TypeConstructor + 'static,
+            #where_predicates
+        {
+            #(#signatures)*
+        }
+
+        impl<R, #(#params),*> #trait_ident<R, #(#args),*>
+            for ::viemo::select::NodeSelector<R, #ident<#(#self_args),*>>
+        where
+            R: ::viemo::TypeConstructor + 'static,
+            #where_predicates
+        {
+            #(#methods)*
+        }
+    })
+}
+
+enum FieldKind {
+    Cell,
+    Option,
+    Vec,
+}
+
+/// Returns the kind of cell field and the type of the value inside the cell, or `None` if the
+/// type is not a (optional) cell or a vector of cells.
+fn field_kind(ty: &Type) -> Option<(FieldKind, &Type)> {
+    if let Some(value_type) = cell_value_type(ty) {
+        return Some((FieldKind::Cell, value_type));
+    }
+
+    let (ident, arg) = single_type_argument(ty)?;
+    let value_type = cell_value_type(arg)?;
+
+    if ident == "Option" {
+        Some((FieldKind::Option, value_type))
+    } else if ident == "Vec" {
+        Some((FieldKind::Vec, value_type))
+    } else {
+        None
+    }
+}
+
+fn cell_value_type(ty: &Type) -> Option<&Type> {
+    let (ident, arg) = single_type_argument(ty)?;
+
+    if ident == "VersionedCell" {
+        Some(arg)
+    } else {
+        None
+    }
+}
+
+/// Returns the name of the path type `ty` and its only type argument (ignoring lifetimes).
+fn single_type_argument(ty: &Type) -> Option<(&syn::Ident, &Type)> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+
+    let segment = path.path.segments.last()?;
+    let PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+
+    let mut types = arguments.args.iter().filter_map(|arg| match arg {
+        GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    });
+
+    match (types.next(), types.next()) {
+        (Some(ty), None) => Some((&segment.ident, ty)),
+        _ => None,
+    }
+}
+
+fn mentions_lifetime(ty: &Type, lifetime: &Lifetime) -> bool {
+    struct FindLifetime<'a> {
+        lifetime: &'a Lifetime,
+        found: bool,
+    }
+
+    impl<'ast> Visit<'ast> for FindLifetime<'_> {
+        fn visit_lifetime(&mut self, lifetime: &'ast Lifetime) {
+            self.found |= lifetime == self.lifetime;
+        }
+    }
+
+    let mut find = FindLifetime {
+        lifetime,
+        found: false,
+    };
+
+    find.visit_type(ty);
+
+    find.found
+}
+
+fn is_skipped(field: &syn::Field) -> Result<bool, Error> {
+    let mut skip = false;
+
+    for attr in &field.attrs {
+        if attr.path().is_ident("selectors") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    skip = true;
+
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported `selectors` attribute"))
+                }
+            })?;
+        }
+    }
+
+    Ok(skip)
+}
@@ -0,0 +1,197 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Error, Field, GenericParam};
+
+pub fn expand(input: &DeriveInput) -> Result<TokenStream, Error> {
+    let ident = &input.ident;
+    let mut generics = input.generics.clone();
+
+    for param in &mut generics.params {
+        if let GenericParam::Type(def) = param {
+            def.bounds
+                .push(syn::parse_quote!(::viemo::snapshot::SnapshotClone));
+        }
+    }
+
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+
+    let (body, map_cells) = match &input.data {
+        Data::Struct(data) => {
+            let Fields {
+                pattern,
+                construct,
+                copy_pattern,
+                map_cells,
+            } = fields(&data.fields, quote!(#ident))?;
+
+            (
+                quote! {
+                    let #pattern = self;
+
+                    #construct
+                },
+                quote! {
+                    let #pattern = self;
+                    let #copy_pattern = copy;
+
+                    #map_cells
+                },
+            )
+        }
+        Data::Enum(data) => {
+            let mut arms = Vec::new();
+            let mut map_arms = Vec::new();
+
+            for variant in &data.variants {
+                let variant_ident = &variant.ident;
+                let Fields {
+                    pattern,
+                    construct,
+                    copy_pattern,
+                    map_cells,
+                } = fields(&variant.fields, quote!(#ident::#variant_ident))?;
+
+                arms.push(quote!(#pattern => #construct));
+                map_arms.push(quote!((#pattern, #copy_pattern) => { #map_cells }));
+            }
+
+            (
+                quote! {
+                    match self {
+                        #(#arms,)*
+                    }
+                },
+                quote! {
+                    #[allow(unreachable_patterns)]
+                    match (self, copy) {
+                        #(#map_arms,)*
+                        _ => (),
+                    }
+                },
+            )
+        }
+        Data::Union(_) => {
+            return Err(Error::new_spanned(
+                ident,
+                "`SnapshotClone` cannot be derived for unions",
+            ))
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::viemo::snapshot::SnapshotClone for #ident #type_generics
+            #where_clause
+        {
+            #[allow(unused_variables)]
+            fn snapshot_clone(&self, token: ::viemo::snapshot::SnapshotToken<'_>) -> Self {
+                // Fields marked with `#[snapshot(clone)]` must not share cells with the snapshot.
+                #[allow(dead_code)]
+                fn clone_cell_free<T>(value: &T) -> T
+                where
+                    T: ::std::clone::Clone + ::viemo::versioned_cell::CellFree,
+                {
+                    ::std::clone::Clone::clone(value)
+                }
+
+                #body
+            }
+
+            #[allow(unused_variables)]
+            fn map_cells<'viemo_cell, 'viemo_copy>(
+                &'viemo_cell self,
+                copy: &'viemo_copy Self,
+                map: &mut ::viemo::snapshot::CellMap<'viemo_cell, 'viemo_copy>,
+            ) {
+                #map_cells
+            }
+        }
+    })
+}
+
+struct Fields {
+    /// Binds the fields by reference.
+    pattern: TokenStream,
+    /// Constructs a copy from the fields bound by `pattern`.
+    construct: TokenStream,
+    /// Binds the fields of a copy by reference, with a `copy_` prefix.
+    copy_pattern: TokenStream,
+    /// Maps the cells of the fields bound by `pattern` to those bound by `copy_pattern`.
+    map_cells: TokenStream,
+}
+
+/// Returns the patterns and expressions that copy the `fields` of `path`, see [Fields].
+fn fields(fields: &syn::Fields, path: TokenStream) -> Result<Fields, Error> {
+    let (members, bindings): (Vec<_>, Vec<_>) = match fields {
+        syn::Fields::Named(named) => named
+            .named
+            .iter()
+            .map(|field| {
+                let field_ident = field.ident.clone().unwrap();
+
+                (quote!(#field_ident), field_ident)
+            })
+            .unzip(),
+        syn::Fields::Unnamed(unnamed) => (0..unnamed.unnamed.len())
+            .map(|index| {
+                let index = syn::Index::from(index);
+
+                (quote!(#index), format_ident!("field_{}", index))
+            })
+            .unzip(),
+        syn::Fields::Unit => (Vec::new(), Vec::new()),
+    };
+
+    let copy_bindings: Vec<_> = bindings
+        .iter()
+        .map(|binding| format_ident!("copy_{}", binding))
+        .collect();
+
+    let mut copies = Vec::new();
+    let mut map_cells = Vec::new();
+
+    for ((field, binding), copy_binding) in fields.iter().zip(&bindings).zip(&copy_bindings) {
+        if is_clone(field)? {
+            copies.push(quote!(clone_cell_free(#binding)));
+        } else {
+            copies.push(quote!(::viemo::snapshot::SnapshotClone::snapshot_clone(#binding, token)));
+            map_cells.push(quote! {
+                ::viemo::snapshot::SnapshotClone::map_cells(#binding, #copy_binding, map);
+            });
+        }
+    }
+
+    // Named fields are bound with shorthand patterns, which do not trigger
+    // `non_shorthand_field_patterns` in the user's crate.
+    let pattern = match fields {
+        syn::Fields::Named(_) => quote!(#path { #(#bindings),* }),
+        _ => quote!(#path { #(#members: #bindings),* }),
+    };
+
+    Ok(Fields {
+        pattern,
+        construct: quote!(#path { #(#members: #copies),* }),
+        copy_pattern: quote!(#path { #(#members: #copy_bindings),* }),
+        map_cells: quote!(#(#map_cells)*),
+    })
+}
+
+/// Whether the `field` is marked with `#[snapshot(clone)]`.
+fn is_clone(field: &Field) -> Result<bool, Error> {
+    let mut clone = false;
+
+    for attr in &field.attrs {
+        if attr.path().is_ident("snapshot") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("clone") {
+                    clone = true;
+
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported `snapshot` attribute"))
+                }
+            })?;
+        }
+    }
+
+    Ok(clone)
+}
@@ -0,0 +1,149 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::visit_mut::VisitMut;
+use syn::{DeriveInput, Error, GenericParam, Lifetime};
+
+pub fn expand(input: &DeriveInput) -> Result<TokenStream, Error> {
+    let store_lifetime = store_lifetime(input)?;
+    let ident = &input.ident;
+
+    let mut impl_params = Vec::new();
+    let mut self_args = Vec::new();
+    let mut type_args = Vec::new();
+
+    for param in &input.generics.params {
+        match param {
+            GenericParam::Lifetime(def) => {
+                self_args.push(quote!('static));
+
+                if Some(&def.lifetime) == store_lifetime.as_ref() {
+                    type_args.push(quote!('store));
+                } else {
+                    type_args.push(quote!('static));
+                }
+            }
+            GenericParam::Type(def) => {
+                let mut def = def.clone();
+
+                def.attrs.clear();
+                def.eq_token = None;
+                def.default = None;
+                def.bounds.push(syn::parse_quote!('static));
+
+                let ident = &def.ident;
+
+                self_args.push(quote!(#ident));
+                type_args.push(quote!(#ident));
+                impl_params.push(quote!(#def));
+            }
+            GenericParam::Const(def) => {
+                let mut def = def.clone();
+
+                def.attrs.clear();
+                def.eq_token = None;
+                def.default = None;
+
+                let ident = &def.ident;
+
+                self_args.push(quote!(#ident));
+                type_args.push(quote!(#ident));
+                impl_params.push(quote!(#def));
+            }
+        }
+    }
+
+    let mut where_clause = input.generics.where_clause.clone();
+    let mut impl_params: syn::punctuated::Punctuated<GenericParam, syn::Token![,]> =
+        syn::parse_quote!(#(#impl_params),*);
+
+    // Lifetimes cannot be parameters of the impl, as `Type<'store>` must outlive any `'store`.
+    // Replace all references to the type's lifetimes in bounds with `'static`.
+    let mut replace_lifetimes = ReplaceLifetimes {
+        lifetimes: input
+            .generics
+            .lifetimes()
+            .map(|def| def.lifetime.clone())
+            .collect(),
+    };
+
+    for param in impl_params.iter_mut() {
+        replace_lifetimes.visit_generic_param_mut(param);
+    }
+
+    if let Some(where_clause) = &mut where_clause {
+        replace_lifetimes.visit_where_clause_mut(where_clause);
+    }
+
+    Ok(quote! {
+        impl<#impl_params> ::viemo::TypeConstructor for #ident<#(#self_args),*> #where_clause {
+            type Type<'store> = #ident<#(#type_args),*>;
+        }
+    })
+}
+
+pub fn store_lifetime(input: &DeriveInput) -> Result<Option<Lifetime>, Error> {
+    for attr in &input.attrs {
+        if attr.path().is_ident("type_constructor") {
+            let mut store_lifetime = None;
+
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("store") {
+                    store_lifetime = Some(meta.value()?.parse::<Lifetime>()?);
+
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported `type_constructor` attribute"))
+                }
+            })?;
+
+            if let Some(lifetime) = &store_lifetime {
+                if !input
+                    .generics
+                    .lifetimes()
+                    .any(|def| &def.lifetime == lifetime)
+                {
+                    return Err(Error::new_spanned(
+                        lifetime,
+                        "the store lifetime must be a lifetime parameter of the type",
+                    ));
+                }
+            }
+
+            return Ok(store_lifetime);
+        }
+    }
+
+    let mut lifetimes = input.generics.lifetimes();
+
+    match (lifetimes.next(), lifetimes.next()) {
+        (None, _) => Ok(None),
+        (Some(def), None) => Ok(Some(def.lifetime.clone())),
+        _ => {
+            if let Some(def) = input
+                .generics
+                .lifetimes()
+                .find(|def| def.lifetime.ident == "store")
+            {
+                Ok(Some(def.lifetime.clone()))
+            } else {
+                Err(Error::new_spanned(
+                    &input.generics,
+                    "cannot determine the store lifetime; mark it with \
+                    `#[type_constructor(store = 'lifetime)]`",
+                ))
+            }
+        }
+    }
+}
+
+pub struct ReplaceLifetimes {
+    pub lifetimes: Vec<Lifetime>,
+}
+
+impl VisitMut for ReplaceLifetimes {
+    fn visit_lifetime_mut(&mut self, lifetime: &mut Lifetime) {
+        if self.lifetimes.contains(lifetime) {
+            *lifetime = Lifetime::new("'static", lifetime.span());
+        }
+    }
+}